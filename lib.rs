@@ -4,11 +4,51 @@ use ink_lang as ink;
 
 #[ink::contract]
 mod staking {
+    use ink_env::call::{build_call, Call, ExecutionInput, Selector};
+    use ink_primitives::{Key, KeyPtr};
     use ink_storage::{
-        traits::{PackedLayout, SpreadAllocate, SpreadLayout},
+        traits::{PackedAllocate, PackedLayout, SpreadAllocate, SpreadLayout},
         Mapping,
     };
 
+    /// Denominator for basis point values. `apy` is the share of the stake, in basis points,
+    /// accrued as reward over a year.
+    pub const BPS_DENOMINATOR: Balance = 10_000;
+
+    /// Upper bound for `apy`, 1000% a year, keeping the reward math far from overflowing.
+    pub const MAX_APY: u64 = 100_000;
+
+    /// Reward multiplier applied to accounts without a custom one, i.e. 1x.
+    pub const DEFAULT_MULTIPLIER_BPS: u16 = 10_000;
+
+    /// Number of stake checkpoints kept per account, older ones are dropped.
+    pub const MAX_STAKE_CHECKPOINTS: usize = 32;
+
+    /// Number of reward checkpoints kept per account, older ones are dropped.
+    pub const MAX_REWARD_CHECKPOINTS: usize = 32;
+
+    /// Seconds in a 365 day year.
+    pub const SECONDS_PER_YEAR: u64 = 31_536_000;
+
+    /// Milliseconds in a 365 day year, the unit timestamp accrual runs in.
+    const MILLIS_PER_YEAR: u64 = SECONDS_PER_YEAR * 1000;
+
+    /// Blocks in a year assuming 6 second blocks, the year block accrual spreads `apy` over.
+    pub const BLOCKS_PER_YEAR: u32 = 5_256_000;
+
+    /// Block time `BLOCKS_PER_YEAR` assumes, in milliseconds.
+    const MILLIS_PER_BLOCK: u64 = MILLIS_PER_YEAR / BLOCKS_PER_YEAR as u64;
+
+    /// Fixed point scale of accrued rewards. Rewards accumulate scaled up by `PRECISION` and
+    /// only whole units are paid out, so tiny rates are carried over instead of truncated.
+    pub const PRECISION: u128 = 1_000_000_000_000;
+
+    /// Most entries a single batch operation processes.
+    pub const MAX_BATCH: u32 = 100;
+
+    /// Decimals of the staked token when the deployer does not pass any.
+    pub const DEFAULT_DECIMALS: u8 = 12;
+
     // ===== Events
 
     #[ink(event)]
@@ -29,6 +69,42 @@ mod staking {
         amount: Balance,
     }
 
+    #[ink(event)]
+    pub struct StakeTransferred {
+        from: AccountId,
+        to: AccountId,
+        amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct Compounded {
+        user: AccountId,
+        amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct CodeUpgraded {
+        code_hash: [u8; 32],
+    }
+
+    #[ink(event)]
+    pub struct RewardPoolFunded {
+        funder: AccountId,
+        amount: Balance,
+        block: BlockNumber,
+    }
+
+    #[ink(event)]
+    pub struct TreasuryUpdated {
+        treasury: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct EpochRewardsDistributed {
+        epoch: u32,
+        total: Balance,
+    }
+
     // ===== Errors
 
     #[derive(Debug, PartialEq, Eq, scale::Encode, scale::Decode)]
@@ -46,6 +122,311 @@ mod staking {
     pub struct StakingPosition {
         pub stake_amount: Balance,
         pub last_action_block: BlockNumber,
+        pub last_action_timestamp: Timestamp,
+        pub first_stake_block: BlockNumber,
+        pub first_stake_timestamp: Timestamp,
+        pub pending_rewards: Balance,
+        /// Sub-unit reward carried over between checkpoints, scaled by `PRECISION`.
+        pub reward_remainder: u128,
+        pub lock_start_block: BlockNumber,
+        /// Block of the last stake, from which the boost decays. Unlike `lock_start_block` it
+        /// is not moved by `relock`.
+        pub boost_start_block: BlockNumber,
+        /// Lock period in effect when the position last staked.
+        pub lock_period_at_stake: BlockNumber,
+        pub last_claim_block: Option<BlockNumber>,
+        /// Rewards paid out or compounded over the lifetime of the position.
+        pub lifetime_rewards: Balance,
+        /// `reward_per_weight` at the checkpoint, the position earns on its growth since.
+        pub reward_per_weight_paid: u128,
+    }
+
+    /// How a stake is weighted when computing its reward.
+    #[derive(
+        Debug,
+        Default,
+        Clone,
+        Copy,
+        PartialEq,
+        Eq,
+        scale::Encode,
+        scale::Decode,
+        SpreadLayout,
+        PackedLayout,
+    )]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink_storage::traits::StorageLayout)
+    )]
+    pub enum WeightMode {
+        #[default]
+        Linear,
+        /// Rewards grow with the square root of the stake, dampening large stakers.
+        Sqrt,
+    }
+
+    impl SpreadAllocate for WeightMode {
+        fn allocate_spread(ptr: &mut KeyPtr) -> Self {
+            ink_storage::traits::forward_allocate_packed::<Self>(ptr)
+        }
+    }
+
+    impl PackedAllocate for WeightMode {
+        fn allocate_packed(&mut self, _at: &Key) {}
+    }
+
+    /// What rewards accrue over.
+    #[derive(
+        Debug,
+        Default,
+        Clone,
+        Copy,
+        PartialEq,
+        Eq,
+        scale::Encode,
+        scale::Decode,
+        SpreadLayout,
+        PackedLayout,
+    )]
+    #[cfg_attr(
+        feature = "std",
+        derive(scale_info::TypeInfo, ink_storage::traits::StorageLayout)
+    )]
+    pub enum AccrualBasis {
+        #[default]
+        Blocks,
+        /// Rewards accrue over elapsed seconds, for chains with irregular block times. Accrual
+        /// still stops at `reward_end_block` and compounds like block accrual.
+        Timestamp,
+    }
+
+    impl SpreadAllocate for AccrualBasis {
+        fn allocate_spread(ptr: &mut KeyPtr) -> Self {
+            ink_storage::traits::forward_allocate_packed::<Self>(ptr)
+        }
+    }
+
+    impl PackedAllocate for AccrualBasis {
+        fn allocate_packed(&mut self, _at: &Key) {}
+    }
+
+    /// Claimed rewards released linearly over `duration` blocks from `start_block`.
+    #[derive(Debug, PartialEq, Eq, scale::Encode, scale::Decode, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct VestingSchedule {
+        pub amount: Balance,
+        pub released: Balance,
+        pub start_block: BlockNumber,
+        pub duration: BlockNumber,
+    }
+
+    #[derive(Debug, PartialEq, Eq, scale::Encode, scale::Decode, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct StakeCheckpoint {
+        pub block: BlockNumber,
+        pub stake_amount: Balance,
+        /// Sum of `stake_amount * blocks` from the first checkpoint up to `block`.
+        pub cumulative_stake: u128,
+    }
+
+    #[derive(Debug, PartialEq, Eq, scale::Encode, scale::Decode, SpreadLayout, PackedLayout)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct RewardCheckpoint {
+        pub block: BlockNumber,
+        /// Rewards earned up to `block`, claimed or not.
+        pub earned: Balance,
+        pub pending_rewards: Balance,
+    }
+
+    /// Owner configuration of the contract, see `get_config` and `new_cloned`.
+    #[derive(Debug, Clone, PartialEq, Eq, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct StakingConfig {
+        /// Yearly rate in basis points, scaled up by `PRECISION`.
+        pub apy: u128,
+        pub weighting: WeightMode,
+        pub accrual_basis: AccrualBasis,
+        pub compound_frequency: u32,
+        pub lock_period: BlockNumber,
+        pub early_unstake_penalty_bps: u16,
+        pub min_stake_duration: BlockNumber,
+        pub keeper_fee_bps: u16,
+        pub reward_burn_bps: u16,
+        pub burn_address: AccountId,
+        pub claim_cooldown: BlockNumber,
+        pub max_emission_per_block: Option<Balance>,
+        pub max_unstake_per_tx: Option<Balance>,
+        pub min_stake: Balance,
+        pub reward_min_stake: Balance,
+        pub decimals: u8,
+        pub target_tvl: Balance,
+        pub apy_floor: u64,
+        pub apy_ceiling: u64,
+        pub reward_end_block: Option<BlockNumber>,
+        pub boost_decay_blocks: BlockNumber,
+        pub max_reward_multiple_bps: Option<u16>,
+        pub reward_vesting_blocks: BlockNumber,
+        pub apy_change_delay: BlockNumber,
+        pub idle_expiry_blocks: Option<BlockNumber>,
+        pub referral_bonus: Balance,
+        pub referral_min_stake: Balance,
+        pub referral_lock_blocks: BlockNumber,
+        pub epoch_length: BlockNumber,
+        pub skip_zero_claims: bool,
+    }
+
+    #[derive(Debug, PartialEq, Eq, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct ContractStats {
+        pub total_staked: Balance,
+        pub staker_count: u32,
+        pub reward_pool: Balance,
+        pub total_rewards_paid: Balance,
+        pub apy: u64,
+    }
+
+    #[derive(Debug, PartialEq, Eq, scale::Encode, scale::Decode)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct StakeInfo {
+        pub stake_amount: Balance,
+        /// Reward claimable right now, see `rewards_for_user`.
+        pub rewards: Balance,
+        pub unlock_block: BlockNumber,
+        pub last_action_block: BlockNumber,
+    }
+
+    /// Largest integer whose square does not exceed `value`.
+    fn integer_sqrt(value: u128) -> u128 {
+        if value < 2 {
+            return value;
+        }
+
+        // Newton's method, starting from an estimate above the root
+        let mut x = value;
+        let mut y = value / 2 + (value & 1);
+        while y < x {
+            x = y;
+            y = (x + value / x) / 2;
+        }
+        x
+    }
+
+    /// Unsigned 256-bit integer, just wide enough to hold a product of `u128` operands until it
+    /// is divided back down to a `u128`.
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+    struct U256 {
+        hi: u128,
+        lo: u128,
+    }
+
+    impl U256 {
+        fn from_u128(value: u128) -> Self {
+            U256 { hi: 0, lo: value }
+        }
+
+        /// Full product of two `u128`, computed over 64-bit limbs.
+        fn full_mul(a: u128, b: u128) -> Self {
+            const MASK: u128 = u64::MAX as u128;
+            let (a_hi, a_lo) = (a >> 64, a & MASK);
+            let (b_hi, b_lo) = (b >> 64, b & MASK);
+
+            let low = a_lo * b_lo;
+            let cross_a = a_hi * b_lo;
+            let cross_b = a_lo * b_hi;
+            let mid = (low >> 64) + (cross_a & MASK) + (cross_b & MASK);
+            U256 {
+                hi: a_hi * b_hi + (cross_a >> 64) + (cross_b >> 64) + (mid >> 64),
+                lo: (low & MASK) | (mid << 64),
+            }
+        }
+
+        fn checked_mul_u128(self, rhs: u128) -> Option<Self> {
+            let low = Self::full_mul(self.lo, rhs);
+            let hi = self.hi.checked_mul(rhs)?.checked_add(low.hi)?;
+            Some(U256 { hi, lo: low.lo })
+        }
+
+        fn checked_mul(self, rhs: Self) -> Option<Self> {
+            if self.hi != 0 && rhs.hi != 0 {
+                return None;
+            }
+            let low = Self::full_mul(self.lo, rhs.lo);
+            let cross = self
+                .hi
+                .checked_mul(rhs.lo)?
+                .checked_add(self.lo.checked_mul(rhs.hi)?)?;
+            let hi = low.hi.checked_add(cross)?;
+            Some(U256 { hi, lo: low.lo })
+        }
+
+        fn checked_sub_u128(self, rhs: u128) -> Option<Self> {
+            let (lo, borrow) = self.lo.overflowing_sub(rhs);
+            let hi = self.hi.checked_sub(u128::from(borrow))?;
+            Some(U256 { hi, lo })
+        }
+
+        fn checked_add_u128(self, rhs: u128) -> Option<Self> {
+            let (lo, carry) = self.lo.overflowing_add(rhs);
+            let hi = self.hi.checked_add(u128::from(carry))?;
+            Some(U256 { hi, lo })
+        }
+
+        /// Quotient and remainder of `self / divisor`, `None` for a zero divisor.
+        fn div_rem_u128(self, divisor: u128) -> Option<(Self, u128)> {
+            if divisor == 0 {
+                return None;
+            }
+            if self.hi == 0 {
+                return Some((Self::from_u128(self.lo / divisor), self.lo % divisor));
+            }
+
+            // bitwise long division, the remainder stays below `divisor`
+            let mut quotient = Self::default();
+            let mut remainder = 0u128;
+            for bit in (0..256).rev() {
+                let (half, shift) = if bit >= 128 {
+                    (self.hi, bit - 128)
+                } else {
+                    (self.lo, bit)
+                };
+                let carry = remainder >> 127;
+                remainder = (remainder << 1) | ((half >> shift) & 1);
+                if carry == 1 || remainder >= divisor {
+                    remainder = remainder.wrapping_sub(divisor);
+                    if bit >= 128 {
+                        quotient.hi |= 1 << shift;
+                    } else {
+                        quotient.lo |= 1 << shift;
+                    }
+                }
+            }
+            Some((quotient, remainder))
+        }
+
+        fn to_u128(self) -> Option<u128> {
+            if self.hi == 0 {
+                Some(self.lo)
+            } else {
+                None
+            }
+        }
+
+        /// Quotient of `self / divisor`, `None` when it does not fit in a `u128`.
+        fn checked_div_u128(self, divisor: u128) -> Option<u128> {
+            self.div_rem_u128(divisor)?.0.to_u128()
+        }
+    }
+
+    /// Reward earned by `stake` at `apy` over `blocks`. The product is taken over 256 bits, so
+    /// this only fails when the reward itself does not fit in a `Balance`.
+    fn safe_reward(stake: Balance, apy: u64, blocks: u64) -> Result<Balance, StakingError> {
+        U256::from_u128(stake)
+            .checked_mul_u128(u128::from(apy))
+            .and_then(|reward| reward.checked_mul_u128(u128::from(blocks)))
+            .and_then(|reward| {
+                reward.checked_div_u128(BPS_DENOMINATOR * Balance::from(BLOCKS_PER_YEAR))
+            })
+            .ok_or_else(|| StakingError::Other("reward calculation overflowed".to_owned()))
     }
 
     // ===== Contract storage
@@ -53,476 +434,8418 @@ mod staking {
     #[ink(storage)]
     #[derive(SpreadAllocate)]
     pub struct Staking {
-        apy: u64,
+        owner: AccountId,
+        paused: bool,
+        /// Yearly rate in basis points, scaled up by `PRECISION` so that rates below a basis
+        /// point can be set.
+        apy: u128,
+        weighting: WeightMode,
         stake_positions: Mapping<AccountId, StakingPosition>,
         staked_addresses: Vec<AccountId>,
+        stake_checkpoints: Mapping<AccountId, Vec<StakeCheckpoint>>,
+        reward_checkpoints: Mapping<AccountId, Vec<RewardCheckpoint>>,
+        total_staked: Balance,
+        max_emission_per_block: Option<Balance>,
+        reward_multipliers: Mapping<AccountId, u16>,
+        /// `reward_weight` each position was last counted with in `total_reward_weight`.
+        reward_weights: Mapping<AccountId, Balance>,
+        /// Sum of the `reward_weight` of every position as of its last checkpoint, what the
+        /// emission cap is compared against.
+        total_reward_weight: Balance,
+        /// Effective rate times the milliseconds it applied for, summed since the deployment,
+        /// with a block counting as `MILLIS_PER_BLOCK`. A unit of weight has earned
+        /// `reward_per_weight / (BPS_DENOMINATOR * MILLIS_PER_YEAR)` over that time.
+        reward_per_weight: u128,
+        /// Block and timestamp `reward_per_weight` was last brought up to date at.
+        reward_per_weight_block: BlockNumber,
+        reward_per_weight_timestamp: Timestamp,
+        boost_decay_blocks: BlockNumber,
+        rewards_frozen: bool,
+        rewards_resumed_block: BlockNumber,
+        rewards_resumed_timestamp: Timestamp,
+        reward_pool: Balance,
+        last_funded_block: BlockNumber,
+        total_rewards_paid: Balance,
+        early_unstake_penalty_bps: u16,
+        lock_period: BlockNumber,
+        keeper_fee_bps: u16,
+        claim_cooldown: BlockNumber,
+        min_stake_duration: BlockNumber,
+        reward_burn_bps: u16,
+        burn_address: AccountId,
+        penalty_waiver_until: BlockNumber,
+        reward_min_stake: Balance,
+        /// Smallest position a stake may open or top up to. Existing smaller positions are kept.
+        min_stake: Balance,
+        stake_count: u64,
+        unstake_count: u64,
+        claim_count: u64,
+        pending_owner: Option<AccountId>,
+        accrual_basis: AccrualBasis,
+        reward_end_block: Option<BlockNumber>,
+        compound_frequency: u32,
+        max_unstake_per_tx: Option<Balance>,
+        max_reward_multiple_bps: Option<u16>,
+        acknowledgement_required: bool,
+        acknowledged: Mapping<AccountId, bool>,
+        referral_bonus: Balance,
+        /// Stake a referred account must open with, and keep, for the bonus to be paid.
+        referral_min_stake: Balance,
+        /// Blocks a referral bonus vests for before the referrer can claim it.
+        referral_lock_blocks: BlockNumber,
+        apy_change_delay: BlockNumber,
+        /// APY reduction waiting for `apy_change_delay`, with the block it can be applied at.
+        pending_apy: Option<(u128, BlockNumber)>,
+        /// Shorter `apy_change_delay` waiting for the current one, with the block it can be
+        /// applied at.
+        pending_apy_change_delay: Option<(BlockNumber, BlockNumber)>,
+        deploy_block: BlockNumber,
+        /// Sum of `total_staked * blocks` up to `tvl_checkpoint_block`.
+        tvl_integral: u128,
+        tvl_checkpoint_block: BlockNumber,
+        idle_expiry_blocks: Option<BlockNumber>,
+        /// Positions flagged by `expire_idle`, they stop accruing until their owner stakes again.
+        expired_positions: Mapping<AccountId, bool>,
+        referrers: Mapping<AccountId, AccountId>,
+        referral_counts: Mapping<AccountId, u32>,
+        /// Referral bonus owed for each referred account, with the block it vests at.
+        pending_referral_bonuses: Mapping<AccountId, (Balance, BlockNumber)>,
+        decimals: u8,
+        blacklisted: Mapping<AccountId, bool>,
+        target_tvl: Balance,
+        apy_floor: u64,
+        apy_ceiling: u64,
+        /// Set by `emergency_pause`, blocks everything but unstaking.
+        frozen: bool,
+        /// Set while funds are sent out, so that a call re-entering the contract during a
+        /// transfer is rejected.
+        reentrancy_locked: bool,
+        reward_vesting_blocks: BlockNumber,
+        vesting: Mapping<AccountId, VestingSchedule>,
+        /// Claimed rewards held in vesting schedules, no longer part of `reward_pool`.
+        total_vesting: Balance,
+        /// Contract notified through `on_stake_change(account, new_balance)` of stake changes.
+        observer: Option<AccountId>,
+        /// Contract asked through `pull(amount)` to top up the reward pool when a claim exceeds it.
+        treasury: Option<AccountId>,
+        /// Rewards each account received over its lifetime, kept after a full unstake.
+        claimed_totals: Mapping<AccountId, Balance>,
+        /// Accounts whose accrual the owner suspended, they keep their settled rewards only.
+        accrual_frozen: Mapping<AccountId, bool>,
+        /// Blocks per reward epoch counted from `deploy_block`, 0 disables the epoch events.
+        epoch_length: BlockNumber,
+        current_epoch: u32,
+        /// `total_rewards_paid` when `current_epoch` started.
+        epoch_start_rewards_paid: Balance,
+        skip_zero_claims: bool,
     }
 
     impl Staking {
         #[ink(constructor)]
         pub fn new(apy: u64) -> Self {
+            assert!(apy <= MAX_APY, "APY cannot exceed MAX_APY");
             ink_lang::utils::initialize_contract(|contract: &mut Self| {
-                contract.apy = apy;
+                contract.init_defaults(apy);
             })
         }
 
-        #[ink(message, payable)]
-        pub fn stake(&mut self) -> Result<(), StakingError> {
-            let transferred_amount = self.env().transferred_value();
-            assert!(transferred_amount > 0, "Must stake more than 0");
+        #[ink(constructor)]
+        pub fn new_with_weighting(apy: u64, weighting: WeightMode) -> Self {
+            assert!(apy <= MAX_APY, "APY cannot exceed MAX_APY");
+            ink_lang::utils::initialize_contract(|contract: &mut Self| {
+                contract.init_defaults(apy);
+                contract.weighting = weighting;
+            })
+        }
 
-            let caller = self.env().caller();
-            if let Some(staking_position) = self.stake_positions.get(caller) {
-                let balance = staking_position.stake_amount;
+        #[ink(constructor)]
+        pub fn new_with_decimals(apy: u64, decimals: u8) -> Self {
+            assert!(apy <= MAX_APY, "APY cannot exceed MAX_APY");
+            ink_lang::utils::initialize_contract(|contract: &mut Self| {
+                contract.init_defaults(apy);
+                contract.decimals = decimals;
+            })
+        }
 
-                if let Some(new_balance) = balance.checked_add(transferred_amount) {
-                    let new_staking_position = StakingPosition {
-                        stake_amount: new_balance,
-                        last_action_block: staking_position.last_action_block,
-                    };
-                    self.stake_positions.insert(caller, &new_staking_position);
-                } else {
-                    return Err(StakingError::Other(
-                        "Failed while adding balances".to_owned(),
-                    ));
+        /// With `skip_zero_claims` a claim with nothing to pay and nothing to checkpoint returns
+        /// without writing to storage.
+        #[ink(constructor)]
+        pub fn new_with_skip_zero_claims(apy: u64, skip_zero_claims: bool) -> Self {
+            assert!(apy <= MAX_APY, "APY cannot exceed MAX_APY");
+            ink_lang::utils::initialize_contract(|contract: &mut Self| {
+                contract.init_defaults(apy);
+                contract.skip_zero_claims = skip_zero_claims;
+            })
+        }
+
+        /// Starts with the configuration of the already deployed `source` contract, e.g. for a
+        /// redeployment. Positions and balances are not copied.
+        #[ink(constructor)]
+        pub fn new_cloned(source: AccountId) -> Self {
+            let config = build_call::<Environment>()
+                .call_type(Call::new().callee(source))
+                .exec_input(ExecutionInput::new(Selector::new(
+                    ink_lang::selector_bytes!("get_config"),
+                )))
+                .returns::<StakingConfig>()
+                .fire()
+                .expect("Failed to read the configuration of the source contract");
+            assert!(
+                config.apy <= u128::from(MAX_APY) * PRECISION,
+                "APY cannot exceed MAX_APY"
+            );
+            ink_lang::utils::initialize_contract(|contract: &mut Self| {
+                contract.init_defaults(0);
+                contract.apply_config(config);
+            })
+        }
+
+        #[ink(constructor)]
+        pub fn new_with_positions(
+            apy: u64,
+            positions: Vec<(AccountId, Balance, BlockNumber)>,
+        ) -> Self {
+            assert!(apy <= MAX_APY, "APY cannot exceed MAX_APY");
+            ink_lang::utils::initialize_contract(|contract: &mut Self| {
+                contract.init_defaults(apy);
+
+                for (account, stake_amount, last_action_block) in positions {
+                    assert!(
+                        !contract.stake_positions.contains(account),
+                        "Duplicate account in initial positions"
+                    );
+
+                    contract.store_position(
+                        account,
+                        &StakingPosition {
+                            stake_amount,
+                            last_action_block,
+                            last_action_timestamp: Self::env().block_timestamp(),
+                            first_stake_block: last_action_block,
+                            first_stake_timestamp: Self::env().block_timestamp(),
+                            // the accumulator starts at the deployment, the blocks before it are
+                            // credited up front
+                            pending_rewards: Self::reward_at_block(
+                                stake_amount,
+                                apy,
+                                last_action_block,
+                                Self::env().block_number(),
+                            ),
+                            reward_remainder: 0,
+                            lock_start_block: last_action_block,
+                            boost_start_block: last_action_block,
+                            lock_period_at_stake: 0,
+                            last_claim_block: None,
+                            lifetime_rewards: 0,
+                            reward_per_weight_paid: 0,
+                        },
+                    );
+                    contract.staked_addresses.push(account);
+                    contract.record_stake_checkpoint(account, stake_amount);
+                    contract.total_staked = contract
+                        .total_staked
+                        .checked_add(stake_amount)
+                        .expect("Overflow while adding initial positions");
                 }
-            } else {
-                self.stake_positions.insert(
-                    caller,
-                    &StakingPosition {
-                        stake_amount: transferred_amount,
-                        last_action_block: self.env().block_number(),
-                    },
+                assert!(
+                    contract.total_staked <= Self::env().balance(),
+                    "Initial positions exceed the contract balance"
                 );
+            })
+        }
+
+        /// Canonical block reward of `stake` at `apy` between `from_block` and `to_block`, i.e. what
+        /// a position with a linear weighting, no multiplier and no emission cap accrues. Saturates
+        /// instead of overflowing, so that clients can reproduce it off-chain.
+        pub fn reward_at_block(
+            stake: Balance,
+            apy: u64,
+            from_block: BlockNumber,
+            to_block: BlockNumber,
+        ) -> Balance {
+            safe_reward(stake, apy, to_block.saturating_sub(from_block).into())
+                .unwrap_or(Balance::MAX)
+        }
+
+        /// Sets every field that is not a `Mapping` explicitly, so that all constructors start
+        /// from the same state.
+        fn init_defaults(&mut self, apy: u64) {
+            self.owner = Self::env().caller();
+            self.paused = false;
+            self.apy = u128::from(apy) * PRECISION;
+            self.weighting = WeightMode::default();
+            self.staked_addresses = Vec::new();
+            self.total_staked = 0;
+            self.max_emission_per_block = None;
+            self.total_reward_weight = 0;
+            self.reward_per_weight = 0;
+            self.reward_per_weight_block = Self::env().block_number();
+            self.reward_per_weight_timestamp = Self::env().block_timestamp();
+            self.boost_decay_blocks = 0;
+            self.rewards_frozen = false;
+            self.rewards_resumed_block = 0;
+            self.rewards_resumed_timestamp = 0;
+            self.reward_pool = 0;
+            self.last_funded_block = 0;
+            self.total_rewards_paid = 0;
+            self.early_unstake_penalty_bps = 0;
+            self.lock_period = 0;
+            self.keeper_fee_bps = 0;
+            self.claim_cooldown = 0;
+            self.min_stake_duration = 0;
+            self.reward_burn_bps = 0;
+            self.burn_address = AccountId::from([0u8; 32]);
+            self.penalty_waiver_until = 0;
+            self.reward_min_stake = 0;
+            self.min_stake = 0;
+            self.stake_count = 0;
+            self.unstake_count = 0;
+            self.claim_count = 0;
+            self.pending_owner = None;
+            self.accrual_basis = AccrualBasis::default();
+            self.reward_end_block = None;
+            self.compound_frequency = 0;
+            self.max_unstake_per_tx = None;
+            self.max_reward_multiple_bps = None;
+            self.acknowledgement_required = false;
+            self.referral_bonus = 0;
+            self.referral_min_stake = 0;
+            self.referral_lock_blocks = 0;
+            self.apy_change_delay = 0;
+            self.pending_apy = None;
+            self.pending_apy_change_delay = None;
+            self.deploy_block = Self::env().block_number();
+            self.tvl_integral = 0;
+            self.tvl_checkpoint_block = self.deploy_block;
+            self.idle_expiry_blocks = None;
+            self.decimals = DEFAULT_DECIMALS;
+            self.target_tvl = 0;
+            self.apy_floor = 0;
+            self.apy_ceiling = MAX_APY;
+            self.frozen = false;
+            self.reentrancy_locked = false;
+            self.reward_vesting_blocks = 0;
+            self.total_vesting = 0;
+            self.observer = None;
+            self.treasury = None;
+            self.epoch_length = 0;
+            self.current_epoch = 0;
+            self.epoch_start_rewards_paid = 0;
+            self.skip_zero_claims = false;
+        }
+
+        #[ink(message, payable)]
+        pub fn stake(&mut self) -> Result<(), StakingError> {
+            let result = self.try_stake(self.env().caller());
+            Self::revert_on_error(result)
+        }
+
+        fn try_stake(&mut self, caller: AccountId) -> Result<(), StakingError> {
+            if self.acknowledgement_required && !self.has_acknowledged(caller) {
+                return Err(StakingError::Other("must acknowledge first".to_owned()));
             }
 
-            self.staked_addresses.push(caller);
-            self.env().emit_event(Staked {
-                user: self.env().caller(),
-                amount: transferred_amount,
-            });
+            self.stake_for_account(caller)
+        }
+
+        /// A payable message returning `Err` would keep the transferred value without staking
+        /// it, so its rejections panic instead, which reverts the transfer.
+        fn revert_on_error(result: Result<(), StakingError>) -> Result<(), StakingError> {
+            if let Err(e) = result {
+                panic!("{:?}", e)
+            }
 
             Ok(())
         }
 
-        #[ink(message)]
-        pub fn unstake(&mut self, unstake_amount: Balance) -> Result<(), StakingError> {
-            assert!(unstake_amount > 0, "Must unstake more than 0");
+        /// Same as `stake`, recording `referrer` as the one who referred the caller. A first stake
+        /// of at least `referral_min_stake` owes `referrer` the referral bonus, which it can claim
+        /// with `claim_referral_bonus` once it vested.
+        #[ink(message, payable)]
+        pub fn stake_with_referral(&mut self, referrer: AccountId) -> Result<(), StakingError> {
+            let result = self.try_stake_with_referral(referrer);
+            Self::revert_on_error(result)
+        }
 
+        fn try_stake_with_referral(&mut self, referrer: AccountId) -> Result<(), StakingError> {
             let caller = self.env().caller();
-            let staking_position = self.stake_positions.get(&caller);
-            if let Some(user_stake) = staking_position {
-                if unstake_amount > user_stake.stake_amount {
-                    return Err(StakingError::UnstakeError(
-                        "unstake amount cannot be greater than staked amount".to_owned(),
-                    ));
-                } else {
-                    if let Some(rest_stake) = user_stake.stake_amount.checked_sub(unstake_amount) {
-                        if let Err(e) = self.claim_reward() {
-                            return Err(StakingError::Other(format!(
-                                "Failed to claim all the rewards after unstaking: {:?}",
-                                e
-                            )));
-                        }
+            if referrer == caller {
+                return Err(StakingError::Other("cannot refer yourself".to_owned()));
+            }
+            Self::ensure_valid_account(referrer)?;
+            self.ensure_not_contract(referrer)?;
 
-                        // update staking information
-                        if rest_stake == 0 {
-                            let idx = self
-                                .staked_addresses
-                                .iter()
-                                .position(|x| *x == caller)
-                                .unwrap();
-                            self.staked_addresses.remove(idx);
-
-                            self.stake_positions.remove(caller);
-                        } else {
-                            self.stake_positions.insert(
-                                caller,
-                                &StakingPosition {
-                                    stake_amount: rest_stake,
-                                    last_action_block: self.env().block_number(),
-                                },
-                            );
-                        }
+            let first_stake =
+                !self.stake_positions.contains(caller) && !self.referrers.contains(caller);
+            self.try_stake(caller)?;
+            if !first_stake {
+                return Ok(());
+            }
 
-                        if self.env().transfer(caller, unstake_amount).is_err() {
-                            panic!("failed to transfer unstaked amount")
-                        }
+            self.referrers.insert(caller, &referrer);
+            let referral_count = self.referral_count(referrer);
+            self.referral_counts
+                .insert(referrer, &referral_count.saturating_add(1));
 
-                        self.env().emit_event(Unstaked {
-                            user: caller,
-                            amount: unstake_amount,
-                        });
-                    } else {
-                        return Err(StakingError::Other(
-                            "Overflow error while substractiong stakes".to_owned(),
-                        ));
-                    }
-                }
-            } else {
-                return Err(StakingError::UnstakeError(
-                    "can only unstake if user has already staked".to_owned(),
-                ));
+            if self.referral_bonus > 0 && self.get_account_stake(caller) >= self.referral_min_stake
+            {
+                let vesting_block = self
+                    .env()
+                    .block_number()
+                    .saturating_add(self.referral_lock_blocks);
+                self.pending_referral_bonuses
+                    .insert(caller, &(self.referral_bonus, vesting_block));
             }
 
             Ok(())
         }
 
+        /// Pays the caller the bonus for referring `referee`, out of the reward pool. The bonus
+        /// has to have vested and `referee` must still hold `referral_min_stake`.
         #[ink(message)]
-        pub fn claim_reward(&mut self) -> Result<(), StakingError> {
+        pub fn claim_referral_bonus(&mut self, referee: AccountId) -> Result<(), StakingError> {
             let caller = self.env().caller();
-            let reward = self.rewards_for_user(caller);
-
-            if let Some(staking_position) = self.stake_positions.get(caller) {
-                self.stake_positions.insert(
-                    caller,
-                    &StakingPosition {
-                        stake_amount: staking_position.stake_amount,
-                        last_action_block: self.env().block_number(),
-                    },
-                );
-
-                if reward > 0 {
-                    if self.env().transfer(caller, reward).is_err() {
-                        return Err(StakingError::ClaimingRewardError(
-                            "failed to transfer claimed reward to user".to_owned(),
-                        ));
-                    }
+            self.ensure_not_frozen()?;
+            self.ensure_not_blacklisted(caller)?;
+            let (bonus, vesting_block) = match self.pending_referral_bonuses.get(referee) {
+                Some(pending) if self.referrers.get(referee) == Some(caller) => pending,
+                _ => return Err(StakingError::Other("no referral bonus to claim".to_owned())),
+            };
+            if self.env().block_number() < vesting_block {
+                return Err(StakingError::Other(
+                    "referral bonus has not vested yet".to_owned(),
+                ));
+            }
+            if self.get_account_stake(referee) < self.referral_min_stake {
+                return Err(StakingError::Other(
+                    "referred stake is below the referral minimum".to_owned(),
+                ));
+            }
 
-                    self.env().emit_event(Claimed {
-                        amount: reward,
-                        user: caller,
-                    });
-                }
-            } else {
-                return Err(StakingError::ClaimingRewardError(
-                    "user doesnt seem to have a stake".to_owned(),
+            if bonus > self.reward_pool || bonus > self.env().balance() {
+                return Err(StakingError::Other(
+                    "reward pool cannot cover the referral bonus".to_owned(),
                 ));
             }
+            self.pending_referral_bonuses.remove(referee);
+            if self.transfer_out(caller, bonus).is_err() {
+                panic!("failed to transfer referral bonus")
+            }
+            self.reward_pool -= bonus;
+            self.total_rewards_paid = self.total_rewards_paid.saturating_add(bonus);
 
             Ok(())
         }
 
+        /// Referral bonus owed for `referee` and the block it vests at.
         #[ink(message)]
-        pub fn get_account_stake(&self, account: AccountId) -> Balance {
-            match self.stake_positions.get(account) {
-                Some(position) => position.stake_amount,
-                _ => Balance::from(0u128),
-            }
+        pub fn get_pending_referral_bonus(
+            &self,
+            referee: AccountId,
+        ) -> Option<(Balance, BlockNumber)> {
+            self.pending_referral_bonuses.get(referee)
         }
 
         #[ink(message)]
-        pub fn rewards_for_user(&self, user: AccountId) -> Balance {
-            let staking_position = self.stake_positions.get(user);
-            match staking_position {
-                Some(stake) => self.calculate_rewards(&stake),
-                _ => Balance::from(0u128),
-            }
+        pub fn get_referrer(&self, account: AccountId) -> Option<AccountId> {
+            self.referrers.get(account)
         }
 
-        fn calculate_rewards(&self, staking_position: &StakingPosition) -> Balance {
-            let current_block = self.env().block_number();
-            if current_block <= staking_position.last_action_block {
-                return Balance::from(0u128);
+        #[ink(message)]
+        pub fn referral_count(&self, account: AccountId) -> u32 {
+            self.referral_counts.get(account).unwrap_or_default()
+        }
+
+        /// A non-zero bonus needs a non-zero `referral_min_stake` and `referral_lock_blocks`, so
+        /// that every bonus is backed by capital staked for a while and cannot be farmed with
+        /// throwaway accounts.
+        #[ink(message)]
+        pub fn set_referral_bonus(
+            &mut self,
+            referral_bonus: Balance,
+            referral_min_stake: Balance,
+            referral_lock_blocks: BlockNumber,
+        ) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+            if referral_bonus > 0 && (referral_min_stake == 0 || referral_lock_blocks == 0) {
+                return Err(StakingError::Other(
+                    "referral bonus requires a minimum stake and lock".to_owned(),
+                ));
             }
+            self.referral_bonus = referral_bonus;
+            self.referral_min_stake = referral_min_stake;
+            self.referral_lock_blocks = referral_lock_blocks;
 
-            current_block
-                .checked_sub(staking_position.last_action_block)
-                .unwrap()
-                .into()
+            Ok(())
         }
-    }
 
-    #[cfg(test)]
-    mod tests {
-        use super::*;
+        /// `(referral_min_stake, referral_lock_blocks)`
+        #[ink(message)]
+        pub fn get_referral_requirements(&self) -> (Balance, BlockNumber) {
+            (self.referral_min_stake, self.referral_lock_blocks)
+        }
 
-        use ink_lang as ink;
-        use ink_lang::codegen::Env;
+        #[ink(message)]
+        pub fn get_referral_bonus(&self) -> Balance {
+            self.referral_bonus
+        }
+
+        /// Records that the caller accepted the staking terms.
+        #[ink(message)]
+        pub fn acknowledge(&mut self) -> Result<(), StakingError> {
+            self.acknowledged.insert(self.env().caller(), &true);
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn has_acknowledged(&self, account: AccountId) -> bool {
+            self.acknowledged.get(account).unwrap_or_default()
+        }
+
+        /// Requires callers to `acknowledge` the staking terms before they can `stake`.
+        #[ink(message)]
+        pub fn set_acknowledgement_required(&mut self, required: bool) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+            self.acknowledgement_required = required;
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn is_acknowledgement_required(&self) -> bool {
+            self.acknowledgement_required
+        }
+
+        /// Stakes the transferred value on behalf of `beneficiary`.
+        #[ink(message, payable)]
+        pub fn stake_for(&mut self, beneficiary: AccountId) -> Result<(), StakingError> {
+            let result = self
+                .ensure_not_contract(beneficiary)
+                .and_then(|_| self.try_stake(beneficiary));
+            Self::revert_on_error(result)
+        }
+
+        /// Moves `amount` of the caller's unlocked stake to `recipient`. Neither position may
+        /// end up below `min_stake`.
+        #[ink(message)]
+        pub fn transfer_stake(
+            &mut self,
+            recipient: AccountId,
+            amount: Balance,
+        ) -> Result<(), StakingError> {
+            self.ensure_not_contract(recipient)?;
+            assert!(amount > 0, "Must transfer more than 0");
+
+            let caller = self.env().caller();
+            self.ensure_can_move_stake(caller, recipient)?;
+            if caller == recipient {
+                return Err(StakingError::Other(
+                    "cannot transfer stake to yourself".to_owned(),
+                ));
+            }
+            let staking_position = match self.stake_positions.get(caller) {
+                Some(staking_position) => staking_position,
+                None => {
+                    return Err(StakingError::Other(
+                        "user doesnt seem to have a stake".to_owned(),
+                    ))
+                }
+            };
+            if amount >= staking_position.stake_amount {
+                return Err(StakingError::Other(
+                    "cannot transfer the full stake, use migrate_position instead".to_owned(),
+                ));
+            }
+            if self.env().block_number() < Self::unlock_block(&staking_position) {
+                return Err(StakingError::Other("stake is still locked".to_owned()));
+            }
+            if staking_position.stake_amount - amount < self.min_stake {
+                return Err(StakingError::Other(
+                    "remaining stake would be below the minimum stake".to_owned(),
+                ));
+            }
+            if self.get_account_stake(recipient).saturating_add(amount) < self.min_stake {
+                return Err(StakingError::Other(
+                    "stake below the minimum stake".to_owned(),
+                ));
+            }
+
+            // rewards accrued so far stay with the sender
+            self.settle_rewards(caller)?;
+            let staking_position = self.stake_positions.get(caller).unwrap();
+            let rest_stake = staking_position.stake_amount - amount;
+            self.store_position(
+                caller,
+                &StakingPosition {
+                    stake_amount: rest_stake,
+                    ..staking_position
+                },
+            );
+            self.record_stake_checkpoint(caller, rest_stake);
+            self.set_total_staked(self.total_staked.saturating_sub(amount));
+
+            self.credit_stake(recipient, amount)?;
+
+            self.env().emit_event(StakeTransferred {
+                from: caller,
+                to: recipient,
+                amount,
+            });
+
+            Ok(())
+        }
+
+        /// Moves the caller's whole position, including unclaimed rewards, to `new_account`.
+        #[ink(message)]
+        pub fn migrate_position(&mut self, new_account: AccountId) -> Result<(), StakingError> {
+            self.ensure_not_contract(new_account)?;
+
+            let caller = self.env().caller();
+            self.ensure_can_move_stake(caller, new_account)?;
+            if !self.stake_positions.contains(caller) {
+                return Err(StakingError::Other(
+                    "user doesnt seem to have a stake".to_owned(),
+                ));
+            }
+            if self.stake_positions.contains(new_account) {
+                return Err(StakingError::Other(
+                    "target account already has a stake".to_owned(),
+                ));
+            }
+
+            self.settle_rewards(caller)?;
+            let staking_position = self.stake_positions.get(caller).unwrap();
+            let amount = staking_position.stake_amount;
+
+            self.stake_positions.remove(caller);
+            self.refresh_reward_weight(caller);
+            self.store_position(new_account, &staking_position);
+            if let Some(idx) = self.staker_index(caller) {
+                self.staked_addresses[idx as usize] = new_account;
+            }
+            self.record_stake_checkpoint(caller, 0);
+            self.record_stake_checkpoint(new_account, amount);
+
+            self.env().emit_event(StakeTransferred {
+                from: caller,
+                to: new_account,
+                amount,
+            });
+
+            Ok(())
+        }
+
+        fn stake_for_account(&mut self, account: AccountId) -> Result<(), StakingError> {
+            Self::ensure_valid_account(account)?;
+
+            let transferred_amount = self.env().transferred_value();
+            assert!(transferred_amount > 0, "Must stake more than 0");
+
+            // a new deposit must not hide a shortfall that already exists
+            if self.env().balance().saturating_sub(transferred_amount) < self.total_staked {
+                return Err(StakingError::Other("contract insolvent".to_owned()));
+            }
+
+            self.stake_amount_for(account, transferred_amount)
+        }
+
+        fn stake_amount_for(
+            &mut self,
+            account: AccountId,
+            amount: Balance,
+        ) -> Result<(), StakingError> {
+            if self.paused {
+                return Err(StakingError::Other("contract is paused".to_owned()));
+            }
+            self.ensure_not_frozen()?;
+            self.ensure_not_blacklisted(account)?;
+            if self.get_account_stake(account).saturating_add(amount) < self.min_stake {
+                return Err(StakingError::Other(
+                    "stake below the minimum stake".to_owned(),
+                ));
+            }
+            self.roll_epoch();
+
+            self.credit_stake(account, amount)?;
+            self.stake_count = self.stake_count.saturating_add(1);
+            self.notify_observer(account);
+
+            self.env().emit_event(Staked {
+                user: account,
+                amount,
+            });
+
+            Ok(())
+        }
+
+        /// Imports positions backed by funds the contract already holds, e.g. when migrating
+        /// from another deployment. Fails if the contract could not cover every stake. Every
+        /// entry is validated before the first one is imported, so the import is all or nothing.
+        #[ink(message)]
+        pub fn batch_stake_for(
+            &mut self,
+            positions: Vec<(AccountId, Balance)>,
+        ) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+            Self::ensure_batch_size(positions.len())?;
+            if self.paused {
+                return Err(StakingError::Other("contract is paused".to_owned()));
+            }
+            self.ensure_not_frozen()?;
+
+            let mut required = self.total_staked.saturating_add(self.reward_pool);
+            for (index, (account, amount)) in positions.iter().enumerate() {
+                Self::ensure_valid_account(*account)?;
+                self.ensure_not_contract(*account)?;
+                self.ensure_not_blacklisted(*account)?;
+                if *amount == 0 {
+                    return Err(StakingError::Other("Must stake more than 0".to_owned()));
+                }
+                // the position as it will be once the earlier entries for the account are in
+                let imported: Balance = positions[..index]
+                    .iter()
+                    .filter(|(earlier, _)| earlier == account)
+                    .fold(0, |sum, (_, earlier_amount)| {
+                        sum.saturating_add(*earlier_amount)
+                    });
+                let stake_amount = self
+                    .get_account_stake(*account)
+                    .saturating_add(imported)
+                    .saturating_add(*amount);
+                if stake_amount < self.min_stake {
+                    return Err(StakingError::Other(
+                        "stake below the minimum stake".to_owned(),
+                    ));
+                }
+                required = required.checked_add(*amount).ok_or_else(|| {
+                    StakingError::Other("Failed while adding balances".to_owned())
+                })?;
+            }
+            if required > self.env().balance() {
+                return Err(StakingError::Other(
+                    "positions exceed the contract balance".to_owned(),
+                ));
+            }
+
+            // anything failing past the validation reverts the entries already imported
+            for (account, amount) in positions {
+                if let Err(e) = self.stake_amount_for(account, amount) {
+                    panic!("{:?}", e)
+                }
+            }
+
+            Ok(())
+        }
+
+        /// Adds `amount` to the position of `account`, opening one if needed.
+        fn credit_stake(
+            &mut self,
+            account: AccountId,
+            amount: Balance,
+        ) -> Result<(), StakingError> {
+            let total_staked = match self.total_staked.checked_add(amount) {
+                Some(total_staked) => total_staked,
+                None => {
+                    return Err(StakingError::Other(
+                        "Failed while adding balances".to_owned(),
+                    ))
+                }
+            };
+
+            if self.stake_positions.contains(account) {
+                // rewards accrued so far are computed on the previous stake
+                self.settle_rewards(account)?;
+                self.expired_positions.remove(account);
+                let staking_position = self.stake_positions.get(account).unwrap();
+
+                if let Some(new_balance) = staking_position.stake_amount.checked_add(amount) {
+                    let new_staking_position = StakingPosition {
+                        stake_amount: new_balance,
+                        lock_start_block: self.env().block_number(),
+                        boost_start_block: self.env().block_number(),
+                        lock_period_at_stake: self.lock_period,
+                        ..staking_position
+                    };
+                    self.store_position(account, &new_staking_position);
+                } else {
+                    return Err(StakingError::Other(
+                        "Failed while adding balances".to_owned(),
+                    ));
+                }
+            } else {
+                self.store_position(
+                    account,
+                    &StakingPosition {
+                        stake_amount: amount,
+                        last_action_block: self.env().block_number(),
+                        last_action_timestamp: self.env().block_timestamp(),
+                        first_stake_block: self.env().block_number(),
+                        first_stake_timestamp: self.env().block_timestamp(),
+                        pending_rewards: 0,
+                        reward_remainder: 0,
+                        lock_start_block: self.env().block_number(),
+                        boost_start_block: self.env().block_number(),
+                        lock_period_at_stake: self.lock_period,
+                        last_claim_block: None,
+                        lifetime_rewards: 0,
+                        reward_per_weight_paid: self.current_reward_per_weight(),
+                    },
+                );
+                self.staked_addresses.push(account);
+                self.record_reward_checkpoint(account);
+            }
+
+            self.set_total_staked(total_staked);
+            self.record_stake_checkpoint(account, self.get_account_stake(account));
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn unstake(&mut self, unstake_amount: Balance) -> Result<(), StakingError> {
+            self.withdraw_stake(unstake_amount, self.env().caller(), true)
+                .map(|_| ())
+        }
+
+        /// Same as `unstake` but leaves the reward in the position, to be claimed separately.
+        /// A fully unstaked position is kept until its reward has been claimed.
+        #[ink(message)]
+        pub fn unstake_no_claim(&mut self, unstake_amount: Balance) -> Result<(), StakingError> {
+            self.withdraw_stake(unstake_amount, self.env().caller(), false)
+                .map(|_| ())
+        }
+
+        /// Same as `unstake` but returns `(principal_returned, reward_paid)`.
+        #[ink(message)]
+        pub fn unstake_detailed(
+            &mut self,
+            unstake_amount: Balance,
+        ) -> Result<(Balance, Balance), StakingError> {
+            self.withdraw_stake(unstake_amount, self.env().caller(), true)
+        }
+
+        /// Same as `unstake` but sends the principal to `recipient`. The reward is still paid
+        /// out to the caller.
+        #[ink(message)]
+        pub fn unstake_to(
+            &mut self,
+            unstake_amount: Balance,
+            recipient: AccountId,
+        ) -> Result<(), StakingError> {
+            Self::ensure_valid_account(recipient)?;
+            self.ensure_not_contract(recipient)?;
+            self.withdraw_stake(unstake_amount, recipient, true)
+                .map(|_| ())
+        }
+
+        /// Returns the principal sent to `recipient` and the reward paid to the caller.
+        fn withdraw_stake(
+            &mut self,
+            unstake_amount: Balance,
+            recipient: AccountId,
+            claim_rewards: bool,
+        ) -> Result<(Balance, Balance), StakingError> {
+            assert!(unstake_amount > 0, "Must unstake more than 0");
+            self.ensure_not_reentered()?;
+            if let Some(max_unstake_per_tx) = self.max_unstake_per_tx {
+                if unstake_amount > max_unstake_per_tx {
+                    return Err(StakingError::Other("exceeds max unstake per tx".to_owned()));
+                }
+            }
+
+            let caller = self.env().caller();
+            let staking_position = self.stake_positions.get(caller);
+            if let Some(user_stake) = staking_position {
+                if unstake_amount > user_stake.stake_amount {
+                    Err(StakingError::UnstakeError(
+                        "unstake amount cannot be greater than staked amount".to_owned(),
+                    ))
+                } else if self.env().block_number() < Self::unlock_block(&user_stake) {
+                    Err(StakingError::UnstakeError(
+                        "stake is still locked".to_owned(),
+                    ))
+                } else if unstake_amount < user_stake.stake_amount
+                    && user_stake.stake_amount - unstake_amount < self.min_stake
+                    && !self.is_capped_exit(user_stake.stake_amount, unstake_amount)
+                {
+                    Err(StakingError::UnstakeError(
+                        "remaining stake would be below the minimum stake".to_owned(),
+                    ))
+                } else {
+                    if let Some(rest_stake) = user_stake.stake_amount.checked_sub(unstake_amount) {
+                        // every fallible step runs before the position and the list of stakers
+                        // are touched, so a failure leaves both as they were. A reward that
+                        // cannot be computed is dropped rather than locking the principal
+                        let accrued = self.calculate_rewards(caller, &user_stake).ok();
+                        if let (true, Some(reward)) = (claim_rewards, accrued) {
+                            self.refill_reward_pool(reward);
+                        }
+                        // a reward the pool cannot cover stays pending, the principal is
+                        // returned regardless
+                        let pays_reward = claim_rewards
+                            && matches!(accrued, Some(reward) if reward <= self.reward_pool);
+                        let reward = if pays_reward {
+                            accrued.unwrap_or_default()
+                        } else {
+                            0
+                        };
+                        if unstake_amount.saturating_add(reward) > self.env().balance() {
+                            return Err(StakingError::UnstakeError(
+                                "contract balance cannot cover the unstake".to_owned(),
+                            ));
+                        }
+                        let total_staked = match self.total_staked.checked_sub(unstake_amount) {
+                            Some(total_staked) => total_staked,
+                            None => {
+                                return Err(StakingError::Other(
+                                    "total_staked underflow".to_owned(),
+                                ))
+                            }
+                        };
+
+                        let penalty_bps = if self.is_early_unstake(&user_stake) {
+                            self.early_unstake_penalty_bps
+                        } else {
+                            0
+                        };
+                        // the penalty is taken from the reward, so it cannot be left unclaimed
+                        if !claim_rewards && penalty_bps > 0 {
+                            return Err(StakingError::UnstakeError(
+                                "an early unstake must claim its rewards".to_owned(),
+                            ));
+                        }
+                        self.roll_epoch();
+
+                        let reward_paid = if pays_reward {
+                            match self.claim_reward_for(caller, caller, penalty_bps) {
+                                Ok(reward_paid) => reward_paid,
+                                Err(e) => {
+                                    return Err(StakingError::Other(format!(
+                                        "Failed to claim all the rewards after unstaking: {:?}",
+                                        e
+                                    )))
+                                }
+                            }
+                        } else {
+                            if self.settle_rewards(caller).is_err() {
+                                self.skip_accrual(caller);
+                            }
+                            // an early exit still forfeits its share of the reward left pending
+                            if penalty_bps > 0 {
+                                self.forfeit_pending_rewards(caller, penalty_bps);
+                            }
+                            0
+                        };
+
+                        // update staking information
+                        self.set_total_staked(total_staked);
+                        // the claim or settlement above already moved the checkpoint
+                        let claimed_stake = self.stake_positions.get(caller).unwrap();
+                        if rest_stake == 0 && claimed_stake.pending_rewards == 0 {
+                            self.remove_position(caller);
+                        } else {
+                            self.store_position(
+                                caller,
+                                &StakingPosition {
+                                    stake_amount: rest_stake,
+                                    ..claimed_stake
+                                },
+                            );
+                        }
+
+                        self.record_stake_checkpoint(caller, rest_stake);
+
+                        if self.transfer_out(recipient, unstake_amount).is_err() {
+                            panic!("failed to transfer unstaked amount")
+                        }
+                        self.unstake_count = self.unstake_count.saturating_add(1);
+                        self.notify_observer(caller);
+
+                        self.env().emit_event(Unstaked {
+                            user: caller,
+                            amount: unstake_amount,
+                        });
+
+                        Ok((unstake_amount, reward_paid))
+                    } else {
+                        Err(StakingError::Other(
+                            "Overflow error while substractiong stakes".to_owned(),
+                        ))
+                    }
+                }
+            } else {
+                Err(StakingError::UnstakeError(
+                    "can only unstake if user has already staked".to_owned(),
+                ))
+            }
+        }
+
+        /// Caps the lifetime rewards of every position at `max_reward_multiple_bps` of its current
+        /// stake, `None` removes the cap.
+        #[ink(message)]
+        pub fn set_max_reward_multiple_bps(
+            &mut self,
+            max_reward_multiple_bps: Option<u16>,
+        ) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+
+            self.settle_all_rewards()?;
+            self.max_reward_multiple_bps = max_reward_multiple_bps;
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_max_reward_multiple_bps(&self) -> Option<u16> {
+            self.max_reward_multiple_bps
+        }
+
+        /// Blocks without a stake, claim or compound after which a position can be expired,
+        /// `None` disables expiry.
+        #[ink(message)]
+        pub fn set_idle_expiry_blocks(
+            &mut self,
+            idle_expiry_blocks: Option<BlockNumber>,
+        ) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+            self.idle_expiry_blocks = idle_expiry_blocks;
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_idle_expiry_blocks(&self) -> Option<BlockNumber> {
+            self.idle_expiry_blocks
+        }
+
+        /// Settles the rewards of an idle position and flags it as expired, so that it stops
+        /// accruing. The rewards stay claimable. Only the owner can call it.
+        #[ink(message)]
+        pub fn expire_idle(&mut self, account: AccountId) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+            let idle_expiry_blocks = match self.idle_expiry_blocks {
+                Some(idle_expiry_blocks) => idle_expiry_blocks,
+                None => return Err(StakingError::Other("idle expiry is disabled".to_owned())),
+            };
+            let staking_position = match self.stake_positions.get(account) {
+                Some(staking_position) => staking_position,
+                None => {
+                    return Err(StakingError::Other(
+                        "account doesnt seem to have a stake".to_owned(),
+                    ))
+                }
+            };
+            if self.is_expired(account) {
+                return Err(StakingError::Other("position already expired".to_owned()));
+            }
+
+            let last_activity_block = staking_position
+                .lock_start_block
+                .max(staking_position.last_claim_block.unwrap_or_default());
+            if self.elapsed_blocks(last_activity_block) < idle_expiry_blocks {
+                return Err(StakingError::Other("position is not idle".to_owned()));
+            }
+
+            self.settle_rewards(account)?;
+            self.expired_positions.insert(account, &true);
+            self.refresh_reward_weight(account);
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn is_expired(&self, account: AccountId) -> bool {
+            self.expired_positions.get(account).unwrap_or_default()
+        }
+
+        /// Whether withdrawing `unstake_amount` out of `stake_amount` is the largest step the
+        /// per transaction cap allows towards a full exit. Such a step may leave less than
+        /// `min_stake` behind, otherwise a position above the cap could never exit.
+        fn is_capped_exit(&self, stake_amount: Balance, unstake_amount: Balance) -> bool {
+            matches!(
+                self.max_unstake_per_tx,
+                Some(max_unstake_per_tx)
+                    if stake_amount > max_unstake_per_tx && unstake_amount == max_unstake_per_tx
+            )
+        }
+
+        /// Caps the amount a single `unstake` can withdraw, `None` removes the cap. An unstake of
+        /// exactly the cap is exempt from `min_stake`, so a full exit is always possible.
+        #[ink(message)]
+        pub fn set_max_unstake_per_tx(
+            &mut self,
+            max_unstake_per_tx: Option<Balance>,
+        ) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+            self.max_unstake_per_tx = max_unstake_per_tx;
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_max_unstake_per_tx(&self) -> Option<Balance> {
+            self.max_unstake_per_tx
+        }
+
+        /// Unstakes `bps` basis points of the caller's current stake.
+        #[ink(message)]
+        pub fn unstake_percent(&mut self, bps: u16) -> Result<(), StakingError> {
+            if Balance::from(bps) > BPS_DENOMINATOR {
+                return Err(StakingError::UnstakeError(
+                    "cannot unstake more than 10000 basis points".to_owned(),
+                ));
+            }
+
+            let stake_amount = self.get_account_stake(self.env().caller());
+            if stake_amount == 0 {
+                return Err(StakingError::UnstakeError(
+                    "can only unstake if user has already staked".to_owned(),
+                ));
+            }
+
+            let unstake_amount = stake_amount.saturating_mul(bps.into()) / BPS_DENOMINATOR;
+            if unstake_amount == 0 {
+                return Err(StakingError::UnstakeError(
+                    "unstake amount rounds down to 0".to_owned(),
+                ));
+            }
+
+            self.unstake(unstake_amount)
+        }
+
+        #[ink(message)]
+        pub fn claim_reward(&mut self) -> Result<(), StakingError> {
+            let caller = self.env().caller();
+            self.ensure_not_frozen()?;
+            self.ensure_not_blacklisted(caller)?;
+            self.ensure_claim_cooldown_elapsed(caller)?;
+            if self.skip_zero_claims
+                && self.reward_vesting_blocks == 0
+                && self.is_claim_noop(caller)
+            {
+                return Ok(());
+            }
+
+            self.claim_reward_for(caller, caller, 0).map(|_| ())
+        }
+
+        /// Whether claiming for `account` would neither pay anything nor move its checkpoint.
+        fn is_claim_noop(&self, account: AccountId) -> bool {
+            match self.stake_positions.get(account) {
+                Some(staking_position) => {
+                    staking_position.last_action_block == self.env().block_number()
+                        && self.rewards_for_user(account) == 0
+                }
+                None => false,
+            }
+        }
+
+        #[ink(message)]
+        pub fn get_skip_zero_claims(&self) -> bool {
+            self.skip_zero_claims
+        }
+
+        /// Pays out the part of the caller's vesting rewards that has vested so far.
+        #[ink(message)]
+        pub fn claim_vested(&mut self) -> Result<(), StakingError> {
+            let caller = self.env().caller();
+            self.ensure_not_frozen()?;
+            self.ensure_not_blacklisted(caller)?;
+            self.release_vested(caller).map(|_| ())
+        }
+
+        #[ink(message)]
+        pub fn get_vesting(&self, account: AccountId) -> Option<VestingSchedule> {
+            self.vesting.get(account)
+        }
+
+        /// Makes reward payouts vest linearly over `reward_vesting_blocks` instead of being paid
+        /// at once. `0` disables vesting, existing schedules keep their duration.
+        #[ink(message)]
+        pub fn set_reward_vesting_blocks(
+            &mut self,
+            reward_vesting_blocks: BlockNumber,
+        ) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+            self.reward_vesting_blocks = reward_vesting_blocks;
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_reward_vesting_blocks(&self) -> BlockNumber {
+            self.reward_vesting_blocks
+        }
+
+        #[ink(message)]
+        pub fn get_total_vesting(&self) -> Balance {
+            self.total_vesting
+        }
+
+        /// Transfers the vested, not yet released part of the schedule of `account`.
+        fn release_vested(&mut self, account: AccountId) -> Result<Balance, StakingError> {
+            let schedule = match self.vesting.get(account) {
+                Some(schedule) => schedule,
+                None => return Ok(0),
+            };
+
+            let releasable = self.releasable_vested(account);
+            if releasable == 0 {
+                return Ok(0);
+            }
+            if releasable > self.env().balance() || self.transfer_out(account, releasable).is_err()
+            {
+                return Err(StakingError::ClaimingRewardError(
+                    "failed to transfer vested reward to user".to_owned(),
+                ));
+            }
+
+            let released = schedule.released + releasable;
+            if released == schedule.amount {
+                self.vesting.remove(account);
+            } else {
+                self.vesting.insert(
+                    account,
+                    &VestingSchedule {
+                        released,
+                        ..schedule
+                    },
+                );
+            }
+            self.total_vesting = self.total_vesting.saturating_sub(releasable);
+
+            Ok(releasable)
+        }
+
+        /// Part of the schedule of `account` that vested but was not released yet.
+        fn releasable_vested(&self, account: AccountId) -> Balance {
+            let schedule = match self.vesting.get(account) {
+                Some(schedule) => schedule,
+                None => return 0,
+            };
+
+            let elapsed = self.elapsed_blocks(schedule.start_block);
+            let vested = if elapsed >= schedule.duration {
+                schedule.amount
+            } else {
+                schedule.amount.saturating_mul(elapsed.into()) / Balance::from(schedule.duration)
+            };
+            vested.saturating_sub(schedule.released)
+        }
+
+        /// Adds `amount` to the vesting rewards of `account`. What has not vested yet vests again
+        /// over a full `reward_vesting_blocks` from now, together with `amount`.
+        fn add_vesting(&mut self, account: AccountId, amount: Balance) {
+            let unvested = self
+                .vesting
+                .get(account)
+                .map(|schedule| schedule.amount - schedule.released)
+                .unwrap_or_default();
+            self.vesting.insert(
+                account,
+                &VestingSchedule {
+                    amount: unvested.saturating_add(amount),
+                    released: 0,
+                    start_block: self.env().block_number(),
+                    duration: self.reward_vesting_blocks,
+                },
+            );
+            self.total_vesting = self.total_vesting.saturating_add(amount);
+        }
+
+        /// Same as `claim_reward` but pays the reward out to `recipient`.
+        #[ink(message)]
+        pub fn claim_to(&mut self, recipient: AccountId) -> Result<(), StakingError> {
+            let caller = self.env().caller();
+            self.ensure_not_frozen()?;
+            self.ensure_not_blacklisted(caller)?;
+            self.ensure_claim_cooldown_elapsed(caller)?;
+            self.claim_reward_for(caller, recipient, 0).map(|_| ())
+        }
+
+        /// What `account` could withdraw with `claim_reward` right now, as opposed to the gross
+        /// pending reward returned by `rewards_for_user`.
+        #[ink(message)]
+        pub fn claimable_now(&self, account: AccountId) -> Balance {
+            if self.ensure_not_frozen().is_err()
+                || self.ensure_not_blacklisted(account).is_err()
+                || self.ensure_claim_cooldown_elapsed(account).is_err()
+                || !self.stake_positions.contains(account)
+            {
+                return 0;
+            }
+
+            // claims are all or nothing, a reward the pool cannot cover is not claimable
+            let reward = self.rewards_for_user(account);
+            if self.ensure_reward_pool_covers(reward).is_err() {
+                return 0;
+            }
+            // with vesting the claim only releases what already vested
+            if self.reward_vesting_blocks > 0 {
+                return self.releasable_vested(account);
+            }
+
+            let burned = reward.saturating_mul(self.reward_burn_bps.into()) / BPS_DENOMINATOR;
+            reward - burned
+        }
+
+        /// Sends `reward_burn_bps` of every reward paid out to `burn_address` instead of the
+        /// claimer. The burned share is transferred before the claimer's, and a claim whose burn
+        /// fails pays nothing.
+        #[ink(message)]
+        pub fn set_reward_burn(
+            &mut self,
+            reward_burn_bps: u16,
+            burn_address: AccountId,
+        ) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+            if Balance::from(reward_burn_bps) > BPS_DENOMINATOR {
+                return Err(StakingError::Other(
+                    "burn share cannot exceed 10000 basis points".to_owned(),
+                ));
+            }
+
+            self.reward_burn_bps = reward_burn_bps;
+            self.burn_address = burn_address;
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_reward_burn(&self) -> (u16, AccountId) {
+            (self.reward_burn_bps, self.burn_address)
+        }
+
+        /// Sets the minimum number of blocks between two claims of the same account.
+        #[ink(message)]
+        pub fn set_claim_cooldown(
+            &mut self,
+            claim_cooldown: BlockNumber,
+        ) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+            self.claim_cooldown = claim_cooldown;
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_claim_cooldown(&self) -> BlockNumber {
+            self.claim_cooldown
+        }
+
+        #[ink(message)]
+        pub fn set_early_unstake_penalty(
+            &mut self,
+            penalty_bps: u16,
+            min_stake_duration: BlockNumber,
+        ) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+            if Balance::from(penalty_bps) > BPS_DENOMINATOR {
+                return Err(StakingError::Other(
+                    "penalty cannot exceed 10000 basis points".to_owned(),
+                ));
+            }
+
+            self.early_unstake_penalty_bps = penalty_bps;
+            self.min_stake_duration = min_stake_duration;
+
+            Ok(())
+        }
+
+        /// Changes the lock period for new stakes and top-ups. Existing positions keep the lock
+        /// period they staked under.
+        #[ink(message)]
+        pub fn set_lock_period(&mut self, lock_period: BlockNumber) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+            self.lock_period = lock_period;
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_lock_period(&self) -> BlockNumber {
+            self.lock_period
+        }
+
+        /// Locks the caller's position again for `lock_blocks` from the current block, settling
+        /// the rewards accrued so far. The new lock cannot end before the current one.
+        #[ink(message)]
+        pub fn relock(&mut self, lock_blocks: BlockNumber) -> Result<(), StakingError> {
+            let caller = self.env().caller();
+            let staking_position = match self.stake_positions.get(caller) {
+                Some(staking_position) => staking_position,
+                None => {
+                    return Err(StakingError::Other(
+                        "can only relock if user has already staked".to_owned(),
+                    ))
+                }
+            };
+
+            let current_block = self.env().block_number();
+            if current_block.saturating_add(lock_blocks) < Self::unlock_block(&staking_position) {
+                return Err(StakingError::Other(
+                    "cannot shorten the current lock".to_owned(),
+                ));
+            }
+
+            self.settle_rewards(caller)?;
+            let staking_position = self.stake_positions.get(caller).unwrap();
+            self.store_position(
+                caller,
+                &StakingPosition {
+                    lock_start_block: current_block,
+                    lock_period_at_stake: lock_blocks,
+                    ..staking_position
+                },
+            );
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_unlock_block(&self, account: AccountId) -> Option<BlockNumber> {
+            self.stake_positions
+                .get(account)
+                .map(|staking_position| Self::unlock_block(&staking_position))
+        }
+
+        /// `(first_stake_block, last_action_block)` of `account`, for cohort analysis.
+        #[ink(message)]
+        pub fn staker_blocks(&self, account: AccountId) -> Option<(BlockNumber, BlockNumber)> {
+            self.stake_positions.get(account).map(|staking_position| {
+                (
+                    staking_position.first_stake_block,
+                    staking_position.last_action_block,
+                )
+            })
+        }
+
+        /// Seconds since `account` first staked, 0 without a position.
+        #[ink(message)]
+        pub fn time_staked_seconds(&self, account: AccountId) -> u64 {
+            self.stake_positions
+                .get(account)
+                .map(|staking_position| {
+                    self.env()
+                        .block_timestamp()
+                        .saturating_sub(staking_position.first_stake_timestamp)
+                        / 1000
+                })
+                .unwrap_or_default()
+        }
+
+        /// Blocks left until the position of `account` unlocks, 0 if it is not locked.
+        #[ink(message)]
+        pub fn remaining_lock(&self, account: AccountId) -> BlockNumber {
+            self.get_unlock_block(account)
+                .map(|unlock_block| unlock_block.saturating_sub(self.env().block_number()))
+                .unwrap_or_default()
+        }
+
+        #[ink(message)]
+        pub fn is_locked(&self, account: AccountId) -> bool {
+            self.remaining_lock(account) > 0
+        }
+
+        /// Whether `account` has a position it could unstake from right now.
+        #[ink(message)]
+        pub fn can_unstake(&self, account: AccountId) -> bool {
+            match self.stake_positions.get(account) {
+                Some(staking_position) => {
+                    staking_position.stake_amount > 0
+                        && self.env().block_number() >= Self::unlock_block(&staking_position)
+                }
+                None => false,
+            }
+        }
+
+        /// Waives the early unstake penalty for every unstake before `penalty_waiver_until`.
+        #[ink(message)]
+        pub fn set_penalty_waiver_until(
+            &mut self,
+            penalty_waiver_until: BlockNumber,
+        ) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+            self.penalty_waiver_until = penalty_waiver_until;
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_penalty_waiver_until(&self) -> BlockNumber {
+            self.penalty_waiver_until
+        }
+
+        #[ink(message)]
+        pub fn get_early_unstake_penalty(&self) -> (u16, BlockNumber) {
+            (self.early_unstake_penalty_bps, self.min_stake_duration)
+        }
+
+        /// Pays out the reward accrued by `account` to `recipient`, keeping `penalty_bps` of it
+        /// in the reward pool. With reward vesting the payout goes into the vesting schedule of
+        /// `account` instead.
+        fn claim_reward_for(
+            &mut self,
+            account: AccountId,
+            recipient: AccountId,
+            penalty_bps: u16,
+        ) -> Result<Balance, StakingError> {
+            self.ensure_not_reentered()?;
+            self.roll_epoch();
+            if let Some(staking_position) = self.stake_positions.get(account) {
+                self.refill_reward_pool(self.rewards_for_user(account));
+                let vest = self.reward_vesting_blocks > 0;
+                if vest {
+                    // what already vested is paid out before the rest joins the new schedule
+                    self.release_vested(account)?;
+                }
+
+                let (reward, reward_remainder) = self.accrue_rewards(account, &staking_position)?;
+                let forfeited = reward.saturating_mul(penalty_bps.into()) / BPS_DENOMINATOR;
+                let payout = reward.saturating_sub(forfeited);
+                let burned = payout.saturating_mul(self.reward_burn_bps.into()) / BPS_DENOMINATOR;
+                let user_payout = payout - burned;
+
+                self.ensure_reward_pool_covers(payout)?;
+
+                // the checkpoint is moved before transferring, so that a claim re-entering
+                // during the transfer finds nothing left to pay
+                self.store_position(
+                    account,
+                    &StakingPosition {
+                        last_action_block: self.env().block_number(),
+                        last_action_timestamp: self.env().block_timestamp(),
+                        pending_rewards: 0,
+                        reward_remainder,
+                        last_claim_block: Some(self.env().block_number()),
+                        reward_per_weight_paid: self.current_reward_per_weight(),
+                        lifetime_rewards: staking_position.lifetime_rewards.saturating_add(reward),
+                        ..staking_position
+                    },
+                );
+
+                let transferred_payout = if vest { 0 } else { user_payout };
+                if let Err(e) = self.transfer_reward(recipient, transferred_payout, burned) {
+                    // a failed transfer must not forfeit the accrued reward
+                    self.store_position(account, &staking_position);
+                    return Err(e);
+                }
+                self.record_reward_checkpoint(account);
+                if vest && user_payout > 0 {
+                    self.add_vesting(account, user_payout);
+                }
+                // a position left by `unstake_no_claim` is done once its reward is claimed
+                if staking_position.stake_amount == 0 {
+                    self.remove_position(account);
+                }
+
+                if payout > 0 {
+                    self.reward_pool = self.reward_pool.saturating_sub(payout);
+                    self.total_rewards_paid = self.total_rewards_paid.saturating_add(payout);
+                    self.claim_count = self.claim_count.saturating_add(1);
+                    self.record_claimed(account, user_payout);
+
+                    self.env().emit_event(Claimed {
+                        amount: user_payout,
+                        user: account,
+                    });
+                }
+
+                Ok(user_payout)
+            } else {
+                Err(StakingError::ClaimingRewardError(
+                    "user doesnt seem to have a stake".to_owned(),
+                ))
+            }
+        }
+
+        /// Restakes the caller's accrued reward.
+        #[ink(message)]
+        pub fn compound(&mut self) -> Result<(), StakingError> {
+            self.compound_for(self.env().caller(), None)
+        }
+
+        /// Restakes the accrued reward of `account` on its behalf, paying `keeper_fee_bps` of
+        /// it to the caller.
+        #[ink(message)]
+        pub fn compound_for_with_fee(&mut self, account: AccountId) -> Result<(), StakingError> {
+            self.compound_for(account, Some(self.env().caller()))
+        }
+
+        #[ink(message)]
+        pub fn set_keeper_fee_bps(&mut self, keeper_fee_bps: u16) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+            if Balance::from(keeper_fee_bps) > BPS_DENOMINATOR {
+                return Err(StakingError::Other(
+                    "keeper fee cannot exceed 10000 basis points".to_owned(),
+                ));
+            }
+            self.keeper_fee_bps = keeper_fee_bps;
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_keeper_fee_bps(&self) -> u16 {
+            self.keeper_fee_bps
+        }
+
+        fn compound_for(
+            &mut self,
+            account: AccountId,
+            keeper: Option<AccountId>,
+        ) -> Result<(), StakingError> {
+            self.ensure_not_frozen()?;
+            self.ensure_not_blacklisted(account)?;
+            self.roll_epoch();
+            let staking_position = match self.stake_positions.get(account) {
+                Some(staking_position) => staking_position,
+                None => {
+                    return Err(StakingError::Other(
+                        "user doesnt seem to have a stake".to_owned(),
+                    ))
+                }
+            };
+
+            // the compounded reward becomes principal, so it must come out of the reward pool
+            self.refill_reward_pool(self.rewards_for_user(account));
+            let (reward, reward_remainder) = self.accrue_rewards(account, &staking_position)?;
+            self.ensure_reward_pool_covers(reward)?;
+            let keeper_fee = match keeper {
+                Some(_) => reward.saturating_mul(self.keeper_fee_bps.into()) / BPS_DENOMINATOR,
+                None => 0,
+            };
+            let compounded = reward.saturating_sub(keeper_fee);
+
+            let (stake_amount, total_staked) = match (
+                staking_position.stake_amount.checked_add(compounded),
+                self.total_staked.checked_add(compounded),
+            ) {
+                (Some(stake_amount), Some(total_staked)) => (stake_amount, total_staked),
+                _ => {
+                    return Err(StakingError::Other(
+                        "Failed while adding balances".to_owned(),
+                    ))
+                }
+            };
+
+            if let Some(keeper) = keeper {
+                if keeper_fee > 0
+                    && (keeper_fee > self.env().balance()
+                        || self.transfer_out(keeper, keeper_fee).is_err())
+                {
+                    return Err(StakingError::Other(
+                        "failed to transfer keeper fee".to_owned(),
+                    ));
+                }
+            }
+
+            self.store_position(
+                account,
+                &StakingPosition {
+                    stake_amount,
+                    last_action_block: self.env().block_number(),
+                    last_action_timestamp: self.env().block_timestamp(),
+                    pending_rewards: 0,
+                    reward_remainder,
+                    lifetime_rewards: staking_position.lifetime_rewards.saturating_add(reward),
+                    reward_per_weight_paid: self.current_reward_per_weight(),
+                    ..staking_position
+                },
+            );
+            self.set_total_staked(total_staked);
+            self.reward_pool -= reward;
+            self.total_rewards_paid = self.total_rewards_paid.saturating_add(reward);
+            self.record_stake_checkpoint(account, stake_amount);
+            self.record_reward_checkpoint(account);
+            self.record_claimed(account, compounded);
+
+            if compounded > 0 {
+                self.env().emit_event(Compounded {
+                    user: account,
+                    amount: compounded,
+                });
+            }
+
+            Ok(())
+        }
+
+        /// Rewards `account` has claimed or compounded over its lifetime.
+        #[ink(message)]
+        pub fn total_claimed_by(&self, account: AccountId) -> Balance {
+            self.claimed_totals.get(account).unwrap_or_default()
+        }
+
+        fn record_claimed(&mut self, account: AccountId, amount: Balance) {
+            if amount > 0 {
+                let claimed_total = self.total_claimed_by(account).saturating_add(amount);
+                self.claimed_totals.insert(account, &claimed_total);
+            }
+        }
+
+        /// Rewards are only paid out of the reward pool, never out of the staked principal.
+        fn ensure_reward_pool_covers(&self, reward: Balance) -> Result<(), StakingError> {
+            if reward > self.reward_pool || reward > self.env().balance() {
+                return Err(StakingError::ClaimingRewardError(
+                    "reward pool cannot cover the claim".to_owned(),
+                ));
+            }
+
+            Ok(())
+        }
+
+        /// Sends `burned` to the burn address, then `user_payout` to `recipient`. A failing burn
+        /// leaves nothing transferred, a payout failing after the burn went out panics so that
+        /// the burn is reverted as well.
+        fn transfer_reward(
+            &mut self,
+            recipient: AccountId,
+            user_payout: Balance,
+            burned: Balance,
+        ) -> Result<(), StakingError> {
+            if burned > 0 && self.transfer_out(self.burn_address, burned).is_err() {
+                return Err(StakingError::ClaimingRewardError(
+                    "failed to transfer claimed reward to user".to_owned(),
+                ));
+            }
+            if user_payout > 0 && self.transfer_out(recipient, user_payout).is_err() {
+                if burned > 0 {
+                    panic!("failed to transfer claimed reward to user")
+                }
+                return Err(StakingError::ClaimingRewardError(
+                    "failed to transfer claimed reward to user".to_owned(),
+                ));
+            }
+
+            Ok(())
+        }
+
+        /// Pays out up to `claim_cap` of the caller's reward and restakes the rest.
+        #[ink(message)]
+        pub fn claim_up_to_then_compound(
+            &mut self,
+            claim_cap: Balance,
+        ) -> Result<(), StakingError> {
+            let caller = self.env().caller();
+            self.ensure_not_reentered()?;
+            self.ensure_not_frozen()?;
+            self.ensure_not_blacklisted(caller)?;
+            self.ensure_claim_cooldown_elapsed(caller)?;
+            self.roll_epoch();
+            let staking_position = match self.stake_positions.get(caller) {
+                Some(staking_position) => staking_position,
+                None => {
+                    return Err(StakingError::ClaimingRewardError(
+                        "user doesnt seem to have a stake".to_owned(),
+                    ))
+                }
+            };
+            self.refill_reward_pool(self.rewards_for_user(caller));
+            let vest = self.reward_vesting_blocks > 0;
+            if vest {
+                self.release_vested(caller)?;
+            }
+
+            let (reward, reward_remainder) = self.accrue_rewards(caller, &staking_position)?;
+            let payout = reward.min(claim_cap);
+            let burned = payout.saturating_mul(self.reward_burn_bps.into()) / BPS_DENOMINATOR;
+            let user_payout = payout - burned;
+            let compounded = reward - payout;
+
+            let (stake_amount, total_staked) = match (
+                staking_position.stake_amount.checked_add(compounded),
+                self.total_staked.checked_add(compounded),
+            ) {
+                (Some(stake_amount), Some(total_staked)) => (stake_amount, total_staked),
+                _ => {
+                    return Err(StakingError::Other(
+                        "Failed while adding balances".to_owned(),
+                    ))
+                }
+            };
+
+            // the compounded part leaves the pool as well, it backs the new stake
+            self.ensure_reward_pool_covers(reward)?;
+            self.transfer_reward(caller, if vest { 0 } else { user_payout }, burned)?;
+
+            self.store_position(
+                caller,
+                &StakingPosition {
+                    stake_amount,
+                    last_action_block: self.env().block_number(),
+                    last_action_timestamp: self.env().block_timestamp(),
+                    pending_rewards: 0,
+                    reward_remainder,
+                    last_claim_block: Some(self.env().block_number()),
+                    reward_per_weight_paid: self.current_reward_per_weight(),
+                    lifetime_rewards: staking_position.lifetime_rewards.saturating_add(reward),
+                    ..staking_position
+                },
+            );
+            self.set_total_staked(total_staked);
+            self.reward_pool = self.reward_pool.saturating_sub(reward);
+            self.total_rewards_paid = self.total_rewards_paid.saturating_add(reward);
+            self.record_stake_checkpoint(caller, stake_amount);
+            self.record_reward_checkpoint(caller);
+            self.record_claimed(caller, user_payout.saturating_add(compounded));
+            if vest && user_payout > 0 {
+                self.add_vesting(caller, user_payout);
+            }
+
+            if payout > 0 {
+                self.claim_count = self.claim_count.saturating_add(1);
+                self.env().emit_event(Claimed {
+                    user: caller,
+                    amount: user_payout,
+                });
+            }
+            if compounded > 0 {
+                self.env().emit_event(Compounded {
+                    user: caller,
+                    amount: compounded,
+                });
+            }
+
+            Ok(())
+        }
+
+        #[ink(message, payable)]
+        pub fn fund_rewards(&mut self) -> Result<(), StakingError> {
+            let transferred_amount = self.env().transferred_value();
+            assert!(transferred_amount > 0, "Must fund more than 0");
+
+            self.reward_pool = self
+                .reward_pool
+                .checked_add(transferred_amount)
+                .expect("Failed while adding to the reward pool");
+
+            self.last_funded_block = self.env().block_number();
+            self.env().emit_event(RewardPoolFunded {
+                funder: self.env().caller(),
+                amount: transferred_amount,
+                block: self.last_funded_block,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_account_stake(&self, account: AccountId) -> Balance {
+            match self.stake_positions.get(account) {
+                Some(position) => position.stake_amount,
+                _ => Balance::from(0u128),
+            }
+        }
+
+        /// Time-weighted average stake of `account` over the last `over_blocks` blocks.
+        #[ink(message)]
+        pub fn average_stake(&self, account: AccountId, over_blocks: BlockNumber) -> Balance {
+            if over_blocks == 0 {
+                return self.get_account_stake(account);
+            }
+
+            let checkpoints = self.stake_checkpoints.get(account).unwrap_or_default();
+            let current_block = self.env().block_number();
+            let window_start = current_block.saturating_sub(over_blocks);
+
+            let cumulative = Self::cumulative_stake_at(&checkpoints, current_block)
+                .saturating_sub(Self::cumulative_stake_at(&checkpoints, window_start));
+            cumulative / Balance::from(over_blocks)
+        }
+
+        /// Pending reward of `account` as of a past `block`, interpolated linearly between the
+        /// reward checkpoints around it. Blocks before the oldest kept checkpoint report 0.
+        #[ink(message)]
+        pub fn rewards_at_block(&self, account: AccountId, block: BlockNumber) -> Balance {
+            let mut checkpoints = self.reward_checkpoints.get(account).unwrap_or_default();
+            // the current state closes the history of a live position
+            if let Some(staking_position) = self.stake_positions.get(account) {
+                let pending_rewards = self.rewards_for_user(account);
+                checkpoints.push(RewardCheckpoint {
+                    block: self.env().block_number(),
+                    earned: staking_position
+                        .lifetime_rewards
+                        .saturating_add(pending_rewards),
+                    pending_rewards,
+                });
+            }
+
+            let idx = match checkpoints
+                .iter()
+                .rposition(|checkpoint| checkpoint.block <= block)
+            {
+                Some(idx) => idx,
+                None => return 0,
+            };
+            let from = &checkpoints[idx];
+            match checkpoints.get(idx + 1) {
+                Some(to) if to.block > from.block => {
+                    let accrued = to.earned.saturating_sub(from.earned);
+                    from.pending_rewards.saturating_add(
+                        accrued.saturating_mul((block - from.block).into())
+                            / Balance::from(to.block - from.block),
+                    )
+                }
+                _ => from.pending_rewards,
+            }
+        }
+
+        #[ink(message)]
+        pub fn get_total_staked(&self) -> Balance {
+            self.total_staked
+        }
+
+        #[ink(message)]
+        pub fn get_staker_count(&self) -> u32 {
+            self.staked_addresses.len() as u32
+        }
+
+        /// Stakers with a non-zero stake, i.e. without positions left by `unstake_no_claim` that
+        /// only hold unclaimed rewards.
+        #[ink(message)]
+        pub fn active_staker_count(&self) -> u32 {
+            let mut count = 0;
+            self.for_each_position(|_, staking_position| {
+                if staking_position.stake_amount > 0 {
+                    count += 1;
+                }
+            });
+            count
+        }
+
+        /// Position of `account` in the list of stakers, for resuming paginated iteration.
+        #[ink(message)]
+        pub fn staker_index(&self, account: AccountId) -> Option<u32> {
+            self.staked_addresses
+                .iter()
+                .position(|x| *x == account)
+                .map(|idx| idx as u32)
+        }
+
+        /// Raw positions of up to `limit` stakers starting at index `start`, for off-chain
+        /// backups and audits.
+        #[ink(message)]
+        pub fn dump_positions(
+            &self,
+            start: u32,
+            limit: u32,
+        ) -> Result<Vec<(AccountId, StakingPosition)>, StakingError> {
+            Self::ensure_batch_size(limit as usize)?;
+
+            Ok(self
+                .staked_addresses
+                .iter()
+                .skip(start as usize)
+                .take(limit as usize)
+                .filter_map(|account| {
+                    self.stake_positions
+                        .get(account)
+                        .map(|staking_position| (*account, staking_position))
+                })
+                .collect())
+        }
+
+        /// Number of stakes, unstakes and reward payouts so far.
+        #[ink(message)]
+        pub fn get_activity_counts(&self) -> (u64, u64, u64) {
+            (self.stake_count, self.unstake_count, self.claim_count)
+        }
+
+        #[ink(message)]
+        pub fn get_reward_pool(&self) -> Balance {
+            self.reward_pool
+        }
+
+        #[ink(message)]
+        pub fn get_contract_balance(&self) -> Balance {
+            self.env().balance()
+        }
+
+        #[ink(message)]
+        pub fn get_last_funded_block(&self) -> BlockNumber {
+            self.last_funded_block
+        }
+
+        #[ink(message)]
+        pub fn get_total_rewards_paid(&self) -> Balance {
+            self.total_rewards_paid
+        }
+
+        #[ink(message)]
+        pub fn get_deploy_block(&self) -> BlockNumber {
+            self.deploy_block
+        }
+
+        /// Rate the protocol actually paid since deployment, in the same unit as `apy`:
+        /// `total_rewards_paid` over the average TVL and the elapsed blocks.
+        #[ink(message)]
+        pub fn average_apy_paid(&self) -> u64 {
+            let tvl_integral = self.current_tvl_integral();
+            if tvl_integral == 0 {
+                return 0;
+            }
+
+            let apy = U256::full_mul(
+                self.total_rewards_paid,
+                BPS_DENOMINATOR * Balance::from(BLOCKS_PER_YEAR),
+            )
+            .checked_div_u128(tvl_integral)
+            .unwrap_or(u128::MAX);
+            u64::try_from(apy).unwrap_or(u64::MAX)
+        }
+
+        /// Audit helper checking that the rewards owed to all stakers are covered by the
+        /// reward pool.
+        #[ink(message)]
+        pub fn check_reward_invariant(&self) -> bool {
+            self.total_pending_rewards() <= self.reward_pool
+        }
+
+        /// Share of the reward pool already owed to stakers, in basis points and capped at 10000.
+        /// 0 when the pool is empty.
+        #[ink(message)]
+        pub fn pool_utilization_bps(&self) -> u16 {
+            if self.reward_pool == 0 {
+                return 0;
+            }
+
+            let utilization =
+                self.total_pending_rewards().saturating_mul(BPS_DENOMINATOR) / self.reward_pool;
+            // capped at BPS_DENOMINATOR, so it fits
+            utilization.min(BPS_DENOMINATOR) as u16
+        }
+
+        /// Sum of the rewards currently owed to all stakers.
+        #[ink(message)]
+        pub fn total_pending_rewards(&self) -> Balance {
+            let mut total_pending: Balance = 0;
+            self.for_each_position(|account, staking_position| {
+                let reward = self
+                    .calculate_rewards(*account, staking_position)
+                    .unwrap_or(Balance::MAX);
+                total_pending = total_pending.saturating_add(reward);
+            });
+
+            total_pending
+        }
+
+        /// Yearly rate in whole basis points, see `get_apy_scaled` for the exact one.
+        #[ink(message)]
+        pub fn get_apy(&self) -> u64 {
+            // never above `MAX_APY`, so it fits
+            (self.apy / PRECISION) as u64
+        }
+
+        /// Yearly rate in basis points, scaled up by `PRECISION`.
+        #[ink(message)]
+        pub fn get_apy_scaled(&self) -> u128 {
+            self.apy
+        }
+
+        #[ink(message)]
+        pub fn get_weighting(&self) -> WeightMode {
+            self.weighting
+        }
+
+        #[ink(message)]
+        pub fn set_weighting(&mut self, weighting: WeightMode) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+
+            // rewards accrued so far are kept at the previous weighting
+            self.settle_all_rewards()?;
+            self.weighting = weighting;
+            self.refresh_all_reward_weights();
+
+            Ok(())
+        }
+
+        /// Parameters of the reward formula, `(apy, blocks_per_year, weighting)`, so that
+        /// projections can be computed off-chain. The rate is the effective one, after the TVL
+        /// scaling and the apy bounds, scaled up by `PRECISION`.
+        #[ink(message)]
+        pub fn reward_rate_info(&self) -> (u128, u32, WeightMode) {
+            (self.effective_rate(), BLOCKS_PER_YEAR, self.weighting)
+        }
+
+        #[ink(message)]
+        pub fn set_accrual_basis(
+            &mut self,
+            accrual_basis: AccrualBasis,
+        ) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+
+            // rewards accrued so far are kept on the previous basis
+            self.settle_all_rewards()?;
+            self.accrual_basis = accrual_basis;
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_accrual_basis(&self) -> AccrualBasis {
+            self.accrual_basis
+        }
+
+        #[ink(message)]
+        pub fn get_config(&self) -> StakingConfig {
+            StakingConfig {
+                apy: self.apy,
+                weighting: self.weighting,
+                accrual_basis: self.accrual_basis,
+                compound_frequency: self.compound_frequency,
+                lock_period: self.lock_period,
+                early_unstake_penalty_bps: self.early_unstake_penalty_bps,
+                min_stake_duration: self.min_stake_duration,
+                keeper_fee_bps: self.keeper_fee_bps,
+                reward_burn_bps: self.reward_burn_bps,
+                burn_address: self.burn_address,
+                claim_cooldown: self.claim_cooldown,
+                max_emission_per_block: self.max_emission_per_block,
+                max_unstake_per_tx: self.max_unstake_per_tx,
+                min_stake: self.min_stake,
+                reward_min_stake: self.reward_min_stake,
+                decimals: self.decimals,
+                target_tvl: self.target_tvl,
+                apy_floor: self.apy_floor,
+                apy_ceiling: self.apy_ceiling,
+                reward_end_block: self.reward_end_block,
+                boost_decay_blocks: self.boost_decay_blocks,
+                max_reward_multiple_bps: self.max_reward_multiple_bps,
+                reward_vesting_blocks: self.reward_vesting_blocks,
+                apy_change_delay: self.apy_change_delay,
+                idle_expiry_blocks: self.idle_expiry_blocks,
+                referral_bonus: self.referral_bonus,
+                referral_min_stake: self.referral_min_stake,
+                referral_lock_blocks: self.referral_lock_blocks,
+                epoch_length: self.epoch_length,
+                skip_zero_claims: self.skip_zero_claims,
+            }
+        }
+
+        fn apply_config(&mut self, config: StakingConfig) {
+            self.apy = config.apy;
+            self.weighting = config.weighting;
+            self.accrual_basis = config.accrual_basis;
+            self.compound_frequency = config.compound_frequency;
+            self.lock_period = config.lock_period;
+            self.early_unstake_penalty_bps = config.early_unstake_penalty_bps;
+            self.min_stake_duration = config.min_stake_duration;
+            self.keeper_fee_bps = config.keeper_fee_bps;
+            self.reward_burn_bps = config.reward_burn_bps;
+            self.burn_address = config.burn_address;
+            self.claim_cooldown = config.claim_cooldown;
+            self.max_emission_per_block = config.max_emission_per_block;
+            self.max_unstake_per_tx = config.max_unstake_per_tx;
+            self.min_stake = config.min_stake;
+            self.reward_min_stake = config.reward_min_stake;
+            self.decimals = config.decimals;
+            self.target_tvl = config.target_tvl;
+            self.apy_floor = config.apy_floor;
+            self.apy_ceiling = config.apy_ceiling;
+            self.reward_end_block = config.reward_end_block;
+            self.boost_decay_blocks = config.boost_decay_blocks;
+            self.max_reward_multiple_bps = config.max_reward_multiple_bps;
+            self.reward_vesting_blocks = config.reward_vesting_blocks;
+            self.apy_change_delay = config.apy_change_delay;
+            self.idle_expiry_blocks = config.idle_expiry_blocks;
+            self.referral_bonus = config.referral_bonus;
+            self.referral_min_stake = config.referral_min_stake;
+            self.referral_lock_blocks = config.referral_lock_blocks;
+            self.epoch_length = config.epoch_length;
+            self.skip_zero_claims = config.skip_zero_claims;
+        }
+
+        #[ink(message)]
+        pub fn get_contract_stats(&self) -> ContractStats {
+            ContractStats {
+                total_staked: self.total_staked,
+                staker_count: self.get_staker_count(),
+                reward_pool: self.reward_pool,
+                total_rewards_paid: self.total_rewards_paid,
+                apy: self.get_apy(),
+            }
+        }
+
+        /// Position of the caller, if it has one.
+        #[ink(message)]
+        pub fn get_my_info(&self) -> Option<StakeInfo> {
+            let caller = self.env().caller();
+            self.stake_positions
+                .get(caller)
+                .map(|staking_position| StakeInfo {
+                    stake_amount: staking_position.stake_amount,
+                    rewards: self.rewards_for_user(caller),
+                    unlock_block: Self::unlock_block(&staking_position),
+                    last_action_block: staking_position.last_action_block,
+                })
+        }
+
+        #[ink(message)]
+        pub fn set_apy(&mut self, apy: u64) -> Result<(), StakingError> {
+            self.set_apy_scaled(u128::from(apy) * PRECISION)
+        }
+
+        /// Sets the yearly rate in basis points scaled up by `PRECISION`, for rates below a
+        /// basis point. Otherwise the same as `set_apy`.
+        #[ink(message)]
+        pub fn set_apy_scaled(&mut self, apy: u128) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+            if apy > u128::from(MAX_APY) * PRECISION {
+                return Err(StakingError::Other("APY cannot exceed MAX_APY".to_owned()));
+            }
+
+            // reductions only take effect once stakers had `apy_change_delay` blocks of notice
+            if apy < self.apy && self.apy_change_delay > 0 {
+                let effective_block = self
+                    .env()
+                    .block_number()
+                    .saturating_add(self.apy_change_delay);
+                self.pending_apy = Some((apy, effective_block));
+                return Ok(());
+            }
+
+            self.pending_apy = None;
+            self.update_apy(apy)
+        }
+
+        /// Applies a queued APY reduction once its delay has elapsed. Anyone can call it.
+        #[ink(message)]
+        pub fn apply_pending_apy(&mut self) -> Result<(), StakingError> {
+            match self.pending_apy {
+                Some((apy, effective_block)) if self.env().block_number() >= effective_block => {
+                    self.pending_apy = None;
+                    self.update_apy(apy)
+                }
+                Some(_) => Err(StakingError::Other(
+                    "APY change is still timelocked".to_owned(),
+                )),
+                None => Err(StakingError::Other("no pending APY change".to_owned())),
+            }
+        }
+
+        /// Queued APY reduction in whole basis points, with the block it can be applied at.
+        #[ink(message)]
+        pub fn get_pending_apy(&self) -> Option<(u64, BlockNumber)> {
+            self.pending_apy
+                .map(|(apy, effective_block)| ((apy / PRECISION) as u64, effective_block))
+        }
+
+        /// Longer delays apply at once. A shorter one is queued behind the current delay, so the
+        /// timelock cannot be lifted to push an APY reduction through right away.
+        #[ink(message)]
+        pub fn set_apy_change_delay(
+            &mut self,
+            apy_change_delay: BlockNumber,
+        ) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+
+            if apy_change_delay < self.apy_change_delay {
+                let effective_block = self
+                    .env()
+                    .block_number()
+                    .saturating_add(self.apy_change_delay);
+                self.pending_apy_change_delay = Some((apy_change_delay, effective_block));
+                return Ok(());
+            }
+
+            self.pending_apy_change_delay = None;
+            self.apy_change_delay = apy_change_delay;
+
+            Ok(())
+        }
+
+        /// Applies a queued shorter APY change delay once the current delay has elapsed. Anyone
+        /// can call it.
+        #[ink(message)]
+        pub fn apply_pending_apy_change_delay(&mut self) -> Result<(), StakingError> {
+            match self.pending_apy_change_delay {
+                Some((apy_change_delay, effective_block))
+                    if self.env().block_number() >= effective_block =>
+                {
+                    self.pending_apy_change_delay = None;
+                    self.apy_change_delay = apy_change_delay;
+                    Ok(())
+                }
+                Some(_) => Err(StakingError::Other(
+                    "APY change delay change is still timelocked".to_owned(),
+                )),
+                None => Err(StakingError::Other(
+                    "no pending APY change delay change".to_owned(),
+                )),
+            }
+        }
+
+        #[ink(message)]
+        pub fn get_pending_apy_change_delay(&self) -> Option<(BlockNumber, BlockNumber)> {
+            self.pending_apy_change_delay
+        }
+
+        #[ink(message)]
+        pub fn get_apy_change_delay(&self) -> BlockNumber {
+            self.apy_change_delay
+        }
+
+        /// Updates `total_staked`, first adding the blocks spent at the previous value to the
+        /// TVL integral.
+        fn set_total_staked(&mut self, total_staked: Balance) {
+            // the target TVL scales the rate with the total stake
+            self.update_reward_per_weight();
+            self.tvl_integral = self.current_tvl_integral();
+            self.tvl_checkpoint_block = self.env().block_number();
+            self.total_staked = total_staked;
+        }
+
+        fn current_tvl_integral(&self) -> u128 {
+            let blocks = self.elapsed_blocks(self.tvl_checkpoint_block);
+            self.tvl_integral
+                .saturating_add(self.total_staked.saturating_mul(blocks.into()))
+        }
+
+        fn update_apy(&mut self, apy: u128) -> Result<(), StakingError> {
+            // rewards accrued so far are kept at the previous rate
+            self.settle_all_rewards()?;
+            self.apy = apy;
+
+            Ok(())
+        }
+
+        /// Only applies to new stakes, top-ups and partial unstakes: existing positions below
+        /// `min_stake` are kept, but can only grow back above it or exit fully. Unstaking the
+        /// whole `max_unstake_per_tx` may also leave less than `min_stake` behind.
+        #[ink(message)]
+        pub fn set_min_stake(&mut self, min_stake: Balance) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+            self.min_stake = min_stake;
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_min_stake(&self) -> Balance {
+            self.min_stake
+        }
+
+        /// Positions staking less than `reward_min_stake` stop accruing rewards until they are
+        /// topped back up.
+        #[ink(message)]
+        pub fn set_reward_min_stake(
+            &mut self,
+            reward_min_stake: Balance,
+        ) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+
+            self.settle_all_rewards()?;
+            self.reward_min_stake = reward_min_stake;
+            self.refresh_all_reward_weights();
+
+            Ok(())
+        }
+
+        /// Stops the accrual of rewards at `reward_end_block`, e.g. at the end of a campaign.
+        #[ink(message)]
+        pub fn set_reward_end_block(
+            &mut self,
+            reward_end_block: Option<BlockNumber>,
+        ) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+
+            self.settle_all_rewards()?;
+            self.reward_end_block = reward_end_block;
+
+            Ok(())
+        }
+
+        /// Number of times per year rewards compound, 0 for simple interest.
+        #[ink(message)]
+        pub fn set_compound_frequency(
+            &mut self,
+            compound_frequency: u32,
+        ) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+            if compound_frequency > BLOCKS_PER_YEAR {
+                return Err(StakingError::Other(
+                    "cannot compound more than once per block".to_owned(),
+                ));
+            }
+
+            self.settle_all_rewards()?;
+            self.compound_frequency = compound_frequency;
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_compound_frequency(&self) -> u32 {
+            self.compound_frequency
+        }
+
+        #[ink(message)]
+        pub fn get_reward_end_block(&self) -> Option<BlockNumber> {
+            self.reward_end_block
+        }
+
+        #[ink(message)]
+        pub fn get_reward_min_stake(&self) -> Balance {
+            self.reward_min_stake
+        }
+
+        #[ink(message)]
+        pub fn pause(&mut self) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+            self.paused = true;
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn unpause(&mut self) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+            self.paused = false;
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn is_paused(&self) -> bool {
+            self.paused
+        }
+
+        /// Blocks staking, claiming and compounding at once. Stakers can still unstake.
+        #[ink(message)]
+        pub fn emergency_pause(&mut self) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+            self.frozen = true;
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn emergency_unpause(&mut self) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+            self.frozen = false;
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn is_frozen(&self) -> bool {
+            self.frozen
+        }
+
+        /// Decommissions the contract: every staker gets back their principal plus accrued
+        /// rewards, and whatever is left in the reward pool goes to the owner. When the pool
+        /// cannot cover every accrued reward, it is shared pro rata instead. Vesting schedules
+        /// stay backed and keep vesting, to be released with `claim_vested`.
+        #[ink(message)]
+        pub fn shutdown(&mut self) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+            if !self.paused {
+                return Err(StakingError::Other(
+                    "contract must be paused before shutdown".to_owned(),
+                ));
+            }
+
+            let mut payouts = Vec::new();
+            let mut total_rewards: Balance = 0;
+            for account in self.staked_addresses.iter() {
+                let stake_amount = self.get_account_stake(*account);
+                let reward = match self.stake_positions.get(account) {
+                    Some(staking_position) => {
+                        self.calculate_rewards(*account, &staking_position)?
+                    }
+                    None => 0,
+                };
+                total_rewards = total_rewards.saturating_add(reward);
+                payouts.push((*account, stake_amount, reward));
+            }
+
+            if total_rewards > self.reward_pool {
+                let accrued_rewards = total_rewards;
+                total_rewards = 0;
+                for (_, _, reward) in payouts.iter_mut() {
+                    *reward = U256::full_mul(*reward, self.reward_pool)
+                        .checked_div_u128(accrued_rewards)
+                        .unwrap_or_default();
+                    total_rewards += *reward;
+                }
+            }
+
+            let remaining_pool = self.reward_pool - total_rewards;
+            let total_payout = self
+                .total_staked
+                .saturating_add(total_rewards)
+                .saturating_add(remaining_pool)
+                .saturating_add(self.total_vesting);
+            if total_payout > self.env().balance() {
+                return Err(StakingError::Other(
+                    "contract balance cannot cover the shutdown".to_owned(),
+                ));
+            }
+
+            for (account, stake_amount, reward) in payouts {
+                if self
+                    .transfer_out(account, stake_amount.saturating_add(reward))
+                    .is_err()
+                {
+                    panic!("failed to transfer funds on shutdown")
+                }
+
+                self.stake_positions.remove(account);
+                self.refresh_reward_weight(account);
+                self.record_stake_checkpoint(account, 0);
+
+                self.env().emit_event(Unstaked {
+                    user: account,
+                    amount: stake_amount,
+                });
+                if reward > 0 {
+                    self.env().emit_event(Claimed {
+                        user: account,
+                        amount: reward,
+                    });
+                }
+            }
+
+            if remaining_pool > 0 && self.transfer_out(self.owner, remaining_pool).is_err() {
+                panic!("failed to return the reward pool to the owner")
+            }
+
+            self.staked_addresses.clear();
+            self.set_total_staked(0);
+            self.reward_pool = 0;
+            self.total_rewards_paid = self.total_rewards_paid.saturating_add(total_rewards);
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn set_observer(&mut self, observer: Option<AccountId>) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+            self.observer = observer;
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_observer(&self) -> Option<AccountId> {
+            self.observer
+        }
+
+        #[ink(message)]
+        pub fn set_treasury(&mut self, treasury: AccountId) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+            Self::ensure_valid_account(treasury)?;
+            self.treasury = Some(treasury);
+
+            self.env().emit_event(TreasuryUpdated { treasury });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_treasury(&self) -> Option<AccountId> {
+            self.treasury
+        }
+
+        /// Sets the epoch length and restarts the epoch count at the current block.
+        #[ink(message)]
+        pub fn set_epoch_length(&mut self, epoch_length: BlockNumber) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+            self.epoch_length = epoch_length;
+            self.current_epoch = self.epoch_at(self.env().block_number());
+            self.epoch_start_rewards_paid = self.total_rewards_paid;
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_epoch_length(&self) -> BlockNumber {
+            self.epoch_length
+        }
+
+        #[ink(message)]
+        pub fn get_current_epoch(&self) -> u32 {
+            self.current_epoch
+        }
+
+        fn epoch_at(&self, block: BlockNumber) -> u32 {
+            if self.epoch_length == 0 {
+                return 0;
+            }
+            block.saturating_sub(self.deploy_block) / self.epoch_length
+        }
+
+        /// Closes the current epoch once its last block has passed, emitting what was paid out
+        /// during it. Epochs without any interaction are folded into the one being closed.
+        fn roll_epoch(&mut self) {
+            let epoch = self.epoch_at(self.env().block_number());
+            if epoch <= self.current_epoch {
+                return;
+            }
+
+            self.env().emit_event(EpochRewardsDistributed {
+                epoch: self.current_epoch,
+                total: self
+                    .total_rewards_paid
+                    .saturating_sub(self.epoch_start_rewards_paid),
+            });
+            self.current_epoch = epoch;
+            self.epoch_start_rewards_paid = self.total_rewards_paid;
+        }
+
+        /// Asks the treasury, if any, for what the reward pool lacks to cover `payout`. Whatever
+        /// the treasury transfers during the call is added to the pool. This is best effort: a
+        /// failing treasury leaves the claim to the usual balance checks.
+        fn refill_reward_pool(&mut self, payout: Balance) {
+            let treasury = match self.treasury {
+                Some(treasury) if payout > self.reward_pool => treasury,
+                _ => return,
+            };
+
+            let balance_before = self.env().balance();
+            let _ = build_call::<Environment>()
+                .call_type(Call::new().callee(treasury))
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink_lang::selector_bytes!("pull")))
+                        .push_arg(payout - self.reward_pool),
+                )
+                .returns::<()>()
+                .fire();
+
+            let refilled = self.env().balance().saturating_sub(balance_before);
+            self.reward_pool = self.reward_pool.saturating_add(refilled);
+        }
+
+        /// Calls `on_stake_change(account, new_balance)` on the observer, if any. This is best
+        /// effort: a failing observer must not block staking or unstaking.
+        fn notify_observer(&self, account: AccountId) {
+            if let Some(observer) = self.observer {
+                let _ = build_call::<Environment>()
+                    .call_type(Call::new().callee(observer))
+                    .exec_input(
+                        ExecutionInput::new(Selector::new(ink_lang::selector_bytes!(
+                            "on_stake_change"
+                        )))
+                        .push_arg(account)
+                        .push_arg(self.get_account_stake(account)),
+                    )
+                    .returns::<()>()
+                    .fire();
+            }
+        }
+
+        /// Replaces the contract code while keeping its storage and balance.
+        #[ink(message)]
+        pub fn upgrade(&mut self, code_hash: [u8; 32]) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+
+            if let Err(e) = ink_env::set_code_hash(&code_hash) {
+                return Err(StakingError::Other(format!(
+                    "Failed to set the code hash: {:?}",
+                    e
+                )));
+            }
+
+            self.env().emit_event(CodeUpgraded { code_hash });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn is_owner(&self, account: AccountId) -> bool {
+            account == self.owner
+        }
+
+        /// Nominates `new_owner`, who becomes the owner once it calls `accept_ownership`.
+        #[ink(message)]
+        pub fn transfer_ownership(&mut self, new_owner: AccountId) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+            self.pending_owner = Some(new_owner);
+
+            Ok(())
+        }
+
+        /// One-call owner change for test suites and integrators. Only builds with the `testing`
+        /// feature accept it, release builds change the owner through `transfer_ownership` and
+        /// `accept_ownership`.
+        #[ink(message)]
+        pub fn set_owner(&mut self, new_owner: AccountId) -> Result<(), StakingError> {
+            if !cfg!(feature = "testing") {
+                return Err(StakingError::Other(
+                    "set_owner is only available in testing builds".to_owned(),
+                ));
+            }
+            self.ensure_owner()?;
+            self.owner = new_owner;
+            self.pending_owner = None;
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn accept_ownership(&mut self) -> Result<(), StakingError> {
+            let caller = self.env().caller();
+            if self.pending_owner != Some(caller) {
+                return Err(StakingError::Other(
+                    "only the pending owner can accept ownership".to_owned(),
+                ));
+            }
+
+            self.owner = caller;
+            self.pending_owner = None;
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_pending_owner(&self) -> Option<AccountId> {
+            self.pending_owner
+        }
+
+        #[ink(message)]
+        pub fn set_max_emission_per_block(
+            &mut self,
+            max_emission_per_block: Option<Balance>,
+        ) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+
+            // rewards accrued so far are kept at the previous cap
+            self.settle_all_rewards()?;
+            self.max_emission_per_block = max_emission_per_block;
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_max_emission_per_block(&self) -> Option<Balance> {
+            self.max_emission_per_block
+        }
+
+        /// Above `target_tvl` the APY shrinks in proportion to the total stake, keeping the reward
+        /// spend flat. The rate follows the total stake as it changes. Zero disables the curve.
+        #[ink(message)]
+        pub fn set_target_tvl(&mut self, target_tvl: Balance) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+
+            // rewards accrued so far are kept at the previous rate
+            self.settle_all_rewards()?;
+            self.target_tvl = target_tvl;
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_target_tvl(&self) -> Balance {
+            self.target_tvl
+        }
+
+        /// Bounds the effective APY is clamped to, whatever the dynamic rate would be.
+        #[ink(message)]
+        pub fn set_apy_bounds(
+            &mut self,
+            apy_floor: u64,
+            apy_ceiling: u64,
+        ) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+            if apy_floor > apy_ceiling {
+                return Err(StakingError::Other(
+                    "APY floor cannot exceed the APY ceiling".to_owned(),
+                ));
+            }
+
+            // rewards accrued so far are kept at the previous rate
+            self.settle_all_rewards()?;
+            self.apy_floor = apy_floor;
+            self.apy_ceiling = apy_ceiling;
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_apy_bounds(&self) -> (u64, u64) {
+            (self.apy_floor, self.apy_ceiling)
+        }
+
+        /// `apy * target_tvl / max(total_staked, target_tvl)` clamped to the APY bounds, the rate
+        /// rewards currently accrue at, in whole basis points.
+        #[ink(message)]
+        pub fn effective_apy(&self) -> u64 {
+            // never above `MAX_APY`, so it fits
+            (self.effective_rate() / PRECISION) as u64
+        }
+
+        /// `effective_apy` scaled up by `PRECISION`, the rate the reward math runs on.
+        fn effective_rate(&self) -> u128 {
+            let apy = if self.target_tvl == 0 || self.total_staked <= self.target_tvl {
+                self.apy
+            } else {
+                // the result never exceeds `apy`, so it fits
+                U256::full_mul(self.apy, self.target_tvl)
+                    .checked_div_u128(self.total_staked)
+                    .unwrap_or(self.apy)
+            };
+
+            apy.clamp(
+                u128::from(self.apy_floor) * PRECISION,
+                u128::from(self.apy_ceiling) * PRECISION,
+            )
+        }
+
+        /// Suspends the reward accrual of `account` alone, e.g. during an investigation. Rewards
+        /// accrued so far are kept.
+        #[ink(message)]
+        pub fn freeze_accrual(&mut self, account: AccountId) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+
+            self.settle_rewards(account)?;
+            self.accrual_frozen.insert(account, &true);
+            self.refresh_reward_weight(account);
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn unfreeze_accrual(&mut self, account: AccountId) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+
+            // moves the checkpoint past the frozen blocks without accruing them
+            self.settle_rewards(account)?;
+            self.accrual_frozen.remove(account);
+            self.refresh_reward_weight(account);
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn is_accrual_frozen(&self, account: AccountId) -> bool {
+            self.accrual_frozen.get(account).unwrap_or_default()
+        }
+
+        #[ink(message)]
+        pub fn set_multiplier(
+            &mut self,
+            account: AccountId,
+            multiplier_bps: u16,
+        ) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+
+            // rewards accrued so far are kept at the previous multiplier
+            self.settle_rewards(account)?;
+            self.reward_multipliers.insert(account, &multiplier_bps);
+            self.refresh_reward_weight(account);
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_multiplier(&self, account: AccountId) -> u16 {
+            self.reward_multipliers
+                .get(account)
+                .unwrap_or(DEFAULT_MULTIPLIER_BPS)
+        }
+
+        /// Sets the window over which a multiplier above 1x decays linearly back to 1x since
+        /// the account last staked. `0` disables the decay.
+        #[ink(message)]
+        pub fn set_boost_decay_blocks(
+            &mut self,
+            boost_decay_blocks: BlockNumber,
+        ) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+
+            self.settle_all_rewards()?;
+            self.boost_decay_blocks = boost_decay_blocks;
+            self.refresh_all_reward_weights();
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_boost_decay_blocks(&self) -> BlockNumber {
+            self.boost_decay_blocks
+        }
+
+        #[ink(message)]
+        pub fn current_boost(&self, account: AccountId) -> u16 {
+            let multiplier = self.get_multiplier(account);
+            if multiplier <= DEFAULT_MULTIPLIER_BPS || self.boost_decay_blocks == 0 {
+                return multiplier;
+            }
+
+            let elapsed = match self.stake_positions.get(account) {
+                Some(staking_position) => self
+                    .elapsed_blocks(staking_position.boost_start_block)
+                    .min(self.boost_decay_blocks),
+                None => 0,
+            };
+
+            let extra = u64::from(multiplier - DEFAULT_MULTIPLIER_BPS);
+            let remaining = extra * u64::from(self.boost_decay_blocks - elapsed)
+                / u64::from(self.boost_decay_blocks);
+            DEFAULT_MULTIPLIER_BPS + remaining as u16
+        }
+
+        /// Stake `account` currently earns on, after the weighting and its current boost. A
+        /// position that does not accrue weighs nothing.
+        #[ink(message)]
+        pub fn reward_weight(&self, account: AccountId) -> Balance {
+            let staking_position = match self.stake_positions.get(account) {
+                Some(staking_position) => staking_position,
+                None => return 0,
+            };
+            if staking_position.stake_amount < self.reward_min_stake
+                || self.is_expired(account)
+                || self.is_accrual_frozen(account)
+            {
+                return 0;
+            }
+
+            let weighted_stake = match self.weighting {
+                WeightMode::Linear => staking_position.stake_amount,
+                WeightMode::Sqrt => integer_sqrt(staking_position.stake_amount),
+            };
+            weighted_stake.saturating_mul(self.current_boost(account).into()) / BPS_DENOMINATOR
+        }
+
+        /// Freezes or resumes reward accrual for every staker. Rewards accrued before the freeze
+        /// are settled and remain claimable, blocks elapsed while frozen earn nothing.
+        #[ink(message)]
+        pub fn set_rewards_frozen(&mut self, frozen: bool) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+            if frozen == self.rewards_frozen {
+                return Ok(());
+            }
+
+            if frozen {
+                self.settle_all_rewards()?;
+            } else {
+                // the frozen blocks do not accrue
+                self.update_reward_per_weight();
+                self.rewards_resumed_block = self.env().block_number();
+                self.rewards_resumed_timestamp = self.env().block_timestamp();
+            }
+            self.rewards_frozen = frozen;
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn is_rewards_frozen(&self) -> bool {
+            self.rewards_frozen
+        }
+
+        #[ink(message)]
+        pub fn rewards_for_user(&self, user: AccountId) -> Balance {
+            let staking_position = self.stake_positions.get(user);
+            match staking_position {
+                // a reward too large to compute is reported as the largest possible one
+                Some(stake) => self.calculate_rewards(user, &stake).unwrap_or(Balance::MAX),
+                _ => Balance::from(0u128),
+            }
+        }
+
+        /// Blocks `account` from staking, claiming and compounding. It can still unstake.
+        #[ink(message)]
+        pub fn blacklist(&mut self, account: AccountId) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+            self.blacklisted.insert(account, &true);
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn unblacklist(&mut self, account: AccountId) -> Result<(), StakingError> {
+            self.ensure_owner()?;
+            self.blacklisted.remove(account);
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn is_blacklisted(&self, account: AccountId) -> bool {
+            self.blacklisted.get(account).unwrap_or_default()
+        }
+
+        /// Pending rewards of `accounts`, in the same order, 0 for accounts without a stake.
+        #[ink(message)]
+        pub fn rewards_for_accounts(
+            &self,
+            accounts: Vec<AccountId>,
+        ) -> Result<Vec<Balance>, StakingError> {
+            Self::ensure_batch_size(accounts.len())?;
+
+            Ok(accounts
+                .into_iter()
+                .map(|account| self.rewards_for_user(account))
+                .collect())
+        }
+
+        /// Reward of `account` together with the token decimals, for display purposes.
+        #[ink(message)]
+        pub fn rewards_for_user_scaled(&self, account: AccountId) -> (Balance, u8) {
+            (self.rewards_for_user(account), self.decimals)
+        }
+
+        #[ink(message)]
+        pub fn get_decimals(&self) -> u8 {
+            self.decimals
+        }
+
+        /// Splits the reward of `account` into `(base_reward, bonus_reward)`, the bonus being what
+        /// its multiplier adds on top of the base rate. Rewards settled by an earlier action are
+        /// reported as base.
+        #[ink(message)]
+        pub fn reward_breakdown(&self, account: AccountId) -> (Balance, Balance) {
+            let staking_position = match self.stake_positions.get(account) {
+                Some(staking_position) => staking_position,
+                None => return (0, 0),
+            };
+
+            let total_reward = self.rewards_for_user(account);
+            let boost = self.average_boost(account, &staking_position);
+            let base = Balance::from(DEFAULT_MULTIPLIER_BPS);
+            if boost <= base {
+                return (total_reward, 0);
+            }
+
+            let accrued = total_reward.saturating_sub(staking_position.pending_rewards);
+            let bonus_reward = accrued.saturating_mul(boost - base) / boost;
+            (total_reward - bonus_reward, bonus_reward)
+        }
+
+        /// Stake of `account` plus its unclaimed reward, i.e. its stake after a `compound`.
+        #[ink(message)]
+        pub fn total_value(&self, account: AccountId) -> Balance {
+            self.get_account_stake(account)
+                .saturating_add(self.rewards_for_user(account))
+        }
+
+        /// Blocks the reward pool lasts at the current emission of all stakers, `None` when
+        /// nothing is being emitted.
+        #[ink(message)]
+        pub fn pool_runway_blocks(&self) -> Option<BlockNumber> {
+            if self.rewards_frozen {
+                return None;
+            }
+
+            let mut emission_per_block: Balance = 0;
+            self.for_each_position(|account, staking_position| {
+                if !self.is_expired(*account) && !self.is_accrual_frozen(*account) {
+                    let reward_per_block = self
+                        .reward_per_block(*account, staking_position)
+                        .unwrap_or(Balance::MAX);
+                    emission_per_block = emission_per_block.saturating_add(reward_per_block);
+                }
+            });
+            if emission_per_block == 0 {
+                return None;
+            }
+
+            let blocks = self.reward_pool.saturating_mul(PRECISION) / emission_per_block;
+            Some(BlockNumber::try_from(blocks).unwrap_or(BlockNumber::MAX))
+        }
+
+        #[ink(message)]
+        pub fn blocks_until_next_reward_unit(&self, account: AccountId) -> Option<BlockNumber> {
+            let staking_position = self.stake_positions.get(account)?;
+
+            let reward_per_block = self.reward_per_block(account, &staking_position).ok()?;
+            if reward_per_block == 0 || self.rewards_frozen {
+                return None;
+            }
+            if reward_per_block >= PRECISION {
+                return Some(0);
+            }
+
+            // sub-unit reward already accrued since the last action
+            let (_, accrued) = self.accrue_rewards(account, &staking_position).ok()?;
+            let missing = PRECISION - accrued;
+
+            let blocks = (missing - 1) / reward_per_block + 1;
+            BlockNumber::try_from(blocks).ok()
+        }
+
+        fn calculate_rewards(
+            &self,
+            account: AccountId,
+            staking_position: &StakingPosition,
+        ) -> Result<Balance, StakingError> {
+            Ok(self.accrue_rewards(account, staking_position)?.0)
+        }
+
+        /// Returns the whole reward accrued by the position and the sub-unit remainder to
+        /// carry over to the next checkpoint.
+        fn accrue_rewards(
+            &self,
+            account: AccountId,
+            staking_position: &StakingPosition,
+        ) -> Result<(Balance, u128), StakingError> {
+            let (elapsed, by_timestamp) = match self.accrued_millis(staking_position) {
+                _ if self.is_expired(account) || self.is_accrual_frozen(account) => (0, false),
+                Some(millis) => (millis, true),
+                None => (self.accrued_blocks(staking_position).into(), false),
+            };
+            if elapsed == 0 {
+                return Ok(self.cap_rewards(
+                    staking_position,
+                    staking_position.pending_rewards,
+                    staking_position.reward_remainder,
+                ));
+            }
+
+            let overflowed = || StakingError::Other("reward calculation overflowed".to_owned());
+            let reward_per_weight = self
+                .current_reward_per_weight()
+                .saturating_sub(staking_position.reward_per_weight_paid);
+            let simple_reward =
+                self.position_reward(account, staking_position, reward_per_weight)?;
+            let reward = self
+                .compounded_reward(simple_reward, elapsed, by_timestamp)
+                .ok_or_else(overflowed)?;
+            // only the whole units have to fit in a `Balance`, the scaled value may not
+            let (accrued, reward_remainder) = reward
+                .checked_add_u128(staking_position.reward_remainder)
+                .and_then(|accrued| accrued.div_rem_u128(PRECISION))
+                .ok_or_else(overflowed)?;
+            let pending_rewards = accrued
+                .to_u128()
+                .and_then(|accrued| staking_position.pending_rewards.checked_add(accrued))
+                .ok_or_else(overflowed)?;
+
+            Ok(self.cap_rewards(staking_position, pending_rewards, reward_remainder))
+        }
+
+        /// Limits the rewards of a position so that its lifetime rewards never exceed
+        /// `max_reward_multiple_bps` of its stake.
+        fn cap_rewards(
+            &self,
+            staking_position: &StakingPosition,
+            pending_rewards: Balance,
+            reward_remainder: u128,
+        ) -> (Balance, u128) {
+            if let Some(max_reward_multiple_bps) = self.max_reward_multiple_bps {
+                let max_rewards = staking_position
+                    .stake_amount
+                    .saturating_mul(max_reward_multiple_bps.into())
+                    / BPS_DENOMINATOR;
+                let allowed = max_rewards.saturating_sub(staking_position.lifetime_rewards);
+                if pending_rewards >= allowed {
+                    return (allowed, 0);
+                }
+            }
+
+            (pending_rewards, reward_remainder)
+        }
+
+        /// Turns the simple interest `simple_reward` earned over `elapsed` into compound interest,
+        /// compounding `compound_frequency` times per year. `elapsed` is in milliseconds with
+        /// timestamp accrual and in blocks otherwise. The growth factor is kept over 256 bits,
+        /// `None` only when it does not fit even there.
+        fn compounded_reward(
+            &self,
+            simple_reward: U256,
+            elapsed: u64,
+            by_timestamp: bool,
+        ) -> Option<U256> {
+            let apy = self.effective_rate();
+            if self.compound_frequency == 0 || apy == 0 {
+                return Some(simple_reward);
+            }
+
+            let one = PRECISION;
+            let units_per_year = Self::units_per_year(by_timestamp);
+            let period = units_per_year / u64::from(self.compound_frequency);
+            // interest over `units` as a fraction of `one`
+            let interest = |units: u64| -> Option<u128> {
+                Some(
+                    (apy / BPS_DENOMINATOR).checked_mul(units.into())? / u128::from(units_per_year),
+                )
+            };
+            let growth = one.checked_add(interest(period)?)?;
+            let mut value = U256::from_u128(one);
+            let mut base = U256::from_u128(growth);
+            let mut periods = elapsed / period;
+            while periods > 0 {
+                if periods & 1 == 1 {
+                    value = value.checked_mul(base)?.div_rem_u128(one)?.0;
+                }
+                periods >>= 1;
+                if periods > 0 {
+                    base = base.checked_mul(base)?.div_rem_u128(one)?.0;
+                }
+            }
+            // the last, incomplete period earns simple interest
+            let partial = one.checked_add(interest(elapsed % period)?)?;
+            value = value.checked_mul_u128(partial)?.div_rem_u128(one)?.0;
+
+            // scale the simple reward by compound over simple interest
+            let simple_interest = (apy / BPS_DENOMINATOR)
+                .checked_mul(elapsed.into())?
+                .checked_div(units_per_year.into())?;
+            if simple_interest == 0 {
+                return Some(simple_reward);
+            }
+            simple_reward
+                .checked_mul(value.checked_sub_u128(one)?)?
+                .div_rem_u128(simple_interest)
+                .map(|(reward, _)| reward)
+        }
+
+        /// Moves the reward accrued so far into `pending_rewards` and resets the checkpoint.
+        fn settle_rewards(&mut self, account: AccountId) -> Result<(), StakingError> {
+            if let Some(staking_position) = self.stake_positions.get(account) {
+                let (pending_rewards, reward_remainder) =
+                    self.accrue_rewards(account, &staking_position)?;
+                self.store_position(
+                    account,
+                    &StakingPosition {
+                        pending_rewards,
+                        reward_remainder,
+                        last_action_block: self.env().block_number(),
+                        last_action_timestamp: self.env().block_timestamp(),
+                        reward_per_weight_paid: self.current_reward_per_weight(),
+                        ..staking_position
+                    },
+                );
+                self.record_reward_checkpoint(account);
+            }
+
+            Ok(())
+        }
+
+        fn forfeit_pending_rewards(&mut self, account: AccountId, penalty_bps: u16) {
+            if let Some(staking_position) = self.stake_positions.get(account) {
+                let forfeited = staking_position
+                    .pending_rewards
+                    .saturating_mul(penalty_bps.into())
+                    / BPS_DENOMINATOR;
+                self.store_position(
+                    account,
+                    &StakingPosition {
+                        pending_rewards: staking_position.pending_rewards - forfeited,
+                        ..staking_position
+                    },
+                );
+            }
+        }
+
+        /// Moves the checkpoint of `account` to the current block without accruing the blocks in
+        /// between, for when their reward cannot be computed.
+        fn skip_accrual(&mut self, account: AccountId) {
+            if let Some(staking_position) = self.stake_positions.get(account) {
+                self.store_position(
+                    account,
+                    &StakingPosition {
+                        reward_remainder: 0,
+                        last_action_block: self.env().block_number(),
+                        last_action_timestamp: self.env().block_timestamp(),
+                        reward_per_weight_paid: self.current_reward_per_weight(),
+                        ..staking_position
+                    },
+                );
+                self.record_reward_checkpoint(account);
+            }
+        }
+
+        fn record_reward_checkpoint(&mut self, account: AccountId) {
+            let staking_position = match self.stake_positions.get(account) {
+                Some(staking_position) => staking_position,
+                None => return,
+            };
+            let block = self.env().block_number();
+            let mut checkpoints = self.reward_checkpoints.get(account).unwrap_or_default();
+
+            if checkpoints.last().map(|checkpoint| checkpoint.block) == Some(block) {
+                checkpoints.pop();
+            }
+            if checkpoints.len() >= MAX_REWARD_CHECKPOINTS {
+                checkpoints.remove(0);
+            }
+            checkpoints.push(RewardCheckpoint {
+                block,
+                earned: staking_position
+                    .lifetime_rewards
+                    .saturating_add(staking_position.pending_rewards),
+                pending_rewards: staking_position.pending_rewards,
+            });
+
+            self.reward_checkpoints.insert(account, &checkpoints);
+        }
+
+        fn record_stake_checkpoint(&mut self, account: AccountId, stake_amount: Balance) {
+            let block = self.env().block_number();
+            let mut checkpoints = self.stake_checkpoints.get(account).unwrap_or_default();
+            let cumulative_stake = Self::cumulative_stake_at(&checkpoints, block);
+
+            if checkpoints.last().map(|checkpoint| checkpoint.block) == Some(block) {
+                checkpoints.pop();
+            }
+            if checkpoints.len() >= MAX_STAKE_CHECKPOINTS {
+                checkpoints.remove(0);
+            }
+            checkpoints.push(StakeCheckpoint {
+                block,
+                stake_amount,
+                cumulative_stake,
+            });
+
+            self.stake_checkpoints.insert(account, &checkpoints);
+        }
+
+        /// Cumulative `stake * blocks` at `block`. Blocks before the oldest kept checkpoint
+        /// are treated as having no stake.
+        fn cumulative_stake_at(checkpoints: &[StakeCheckpoint], block: BlockNumber) -> u128 {
+            match checkpoints
+                .iter()
+                .rev()
+                .find(|checkpoint| checkpoint.block <= block)
+            {
+                Some(checkpoint) => checkpoint.cumulative_stake.saturating_add(
+                    checkpoint
+                        .stake_amount
+                        .saturating_mul((block - checkpoint.block).into()),
+                ),
+                None => checkpoints
+                    .first()
+                    .map(|checkpoint| checkpoint.cumulative_stake)
+                    .unwrap_or_default(),
+            }
+        }
+
+        fn remove_position(&mut self, account: AccountId) {
+            if let Some(idx) = self.staked_addresses.iter().position(|x| *x == account) {
+                self.staked_addresses.remove(idx);
+            }
+
+            self.stake_positions.remove(account);
+            self.expired_positions.remove(account);
+            self.refresh_reward_weight(account);
+        }
+
+        fn store_position(&mut self, account: AccountId, staking_position: &StakingPosition) {
+            self.stake_positions.insert(account, staking_position);
+            self.refresh_reward_weight(account);
+        }
+
+        /// Recounts the weight of `account` in `total_reward_weight`, after anything its
+        /// `reward_weight` depends on changed.
+        fn refresh_reward_weight(&mut self, account: AccountId) {
+            let reward_weight = self.reward_weight(account);
+            // the emission cap scales the rate with the total weight
+            self.update_reward_per_weight();
+            let previous = self.reward_weights.get(account).unwrap_or_default();
+            self.total_reward_weight = self
+                .total_reward_weight
+                .saturating_sub(previous)
+                .saturating_add(reward_weight);
+            if reward_weight == 0 {
+                self.reward_weights.remove(account);
+            } else {
+                self.reward_weights.insert(account, &reward_weight);
+            }
+        }
+
+        fn refresh_all_reward_weights(&mut self) {
+            for account in self.staked_addresses.clone() {
+                self.refresh_reward_weight(account);
+            }
+        }
+
+        /// Calls `f` with every staker and its position, in `staked_addresses` order.
+        fn for_each_position<F: FnMut(&AccountId, &StakingPosition)>(&self, mut f: F) {
+            for account in self.staked_addresses.iter() {
+                if let Some(staking_position) = self.stake_positions.get(account) {
+                    f(account, &staking_position);
+                }
+            }
+        }
+
+        fn settle_all_rewards(&mut self) -> Result<(), StakingError> {
+            self.update_reward_per_weight();
+            for account in self.staked_addresses.clone() {
+                self.settle_rewards(account)?;
+            }
+
+            Ok(())
+        }
+
+        /// Number of blocks the position has been earning rewards for since its checkpoint.
+        fn accrued_blocks(&self, staking_position: &StakingPosition) -> BlockNumber {
+            if self.rewards_frozen {
+                return 0;
+            }
+
+            let from = staking_position
+                .last_action_block
+                .max(self.rewards_resumed_block);
+            match self.reward_end_block {
+                Some(reward_end_block) => self
+                    .elapsed_blocks(from)
+                    .min(reward_end_block.saturating_sub(from)),
+                None => self.elapsed_blocks(from),
+            }
+        }
+
+        /// Milliseconds the position has been earning rewards for since its checkpoint, or `None`
+        /// when rewards accrue over blocks.
+        fn accrued_millis(&self, staking_position: &StakingPosition) -> Option<u64> {
+            if self.accrual_basis != AccrualBasis::Timestamp {
+                return None;
+            }
+            if self.rewards_frozen {
+                return Some(0);
+            }
+
+            let (from_block, from) =
+                if staking_position.last_action_block >= self.rewards_resumed_block {
+                    (
+                        staking_position.last_action_block,
+                        staking_position.last_action_timestamp,
+                    )
+                } else {
+                    (self.rewards_resumed_block, self.rewards_resumed_timestamp)
+                };
+            let millis = self.env().block_timestamp().saturating_sub(from);
+
+            // past `reward_end_block` only the share of the time spent before it accrues
+            let blocks = self.elapsed_blocks(from_block);
+            match self.reward_end_block {
+                Some(reward_end_block) if blocks > 0 => {
+                    let accrued_blocks = blocks.min(reward_end_block.saturating_sub(from_block));
+                    let accrued_millis =
+                        u128::from(millis) * u128::from(accrued_blocks) / u128::from(blocks);
+                    // never more than `millis`, so it fits
+                    Some(accrued_millis as u64)
+                }
+                _ => Some(millis),
+            }
+        }
+
+        /// Reward accrued per block, scaled up by `PRECISION`. With timestamp accrual a block
+        /// earns the reward of the milliseconds it takes.
+        fn reward_per_block(
+            &self,
+            account: AccountId,
+            staking_position: &StakingPosition,
+        ) -> Result<Balance, StakingError> {
+            let by_timestamp = self.accrual_basis == AccrualBasis::Timestamp;
+            let units = if by_timestamp {
+                self.millis_per_block(staking_position)
+            } else {
+                1
+            };
+            self.position_reward(
+                account,
+                staking_position,
+                self.reward_per_weight_over(units),
+            )?
+            .to_u128()
+            .ok_or_else(|| StakingError::Other("reward calculation overflowed".to_owned()))
+        }
+
+        /// Average block time since the checkpoint of the position, or the 6 seconds
+        /// `BLOCKS_PER_YEAR` assumes while no block has passed yet.
+        fn millis_per_block(&self, staking_position: &StakingPosition) -> u64 {
+            let blocks = self.elapsed_blocks(staking_position.last_action_block);
+            if blocks == 0 {
+                return MILLIS_PER_BLOCK;
+            }
+            self.env()
+                .block_timestamp()
+                .saturating_sub(staking_position.last_action_timestamp)
+                / u64::from(blocks)
+        }
+
+        /// Units of `elapsed` in a year: milliseconds with timestamp accrual, blocks otherwise.
+        fn units_per_year(by_timestamp: bool) -> u64 {
+            if by_timestamp {
+                MILLIS_PER_YEAR
+            } else {
+                BLOCKS_PER_YEAR.into()
+            }
+        }
+
+        /// Growth of `reward_per_weight` over `units` at the current rate. With timestamp accrual
+        /// `units` are milliseconds, blocks otherwise.
+        ///
+        /// When the accrual of all stakers together would exceed `max_emission_per_block`, the
+        /// rate is lowered so that the aggregate matches the cap.
+        fn reward_per_weight_over(&self, units: u64) -> u128 {
+            let millis = if self.accrual_basis == AccrualBasis::Timestamp {
+                units
+            } else {
+                units.saturating_mul(MILLIS_PER_BLOCK)
+            };
+
+            let mut apy = self.effective_rate();
+            if let Some(max_emission) = self.max_emission_per_block {
+                // the rate at which the weight of all positions together earns exactly the cap
+                let capped_apy = U256::full_mul(
+                    max_emission,
+                    BPS_DENOMINATOR * Balance::from(BLOCKS_PER_YEAR) * PRECISION,
+                )
+                .checked_div_u128(self.total_reward_weight)
+                .unwrap_or(u128::MAX);
+                apy = apy.min(capped_apy);
+            }
+
+            apy.saturating_mul(millis.into())
+        }
+
+        /// Units of accrual since `reward_per_weight` was last brought up to date: none while
+        /// rewards are frozen, and only the share before `reward_end_block`.
+        fn reward_per_weight_elapsed(&self) -> u64 {
+            if self.rewards_frozen {
+                return 0;
+            }
+
+            let from = self.reward_per_weight_block;
+            let blocks = self.elapsed_blocks(from);
+            let accrued_blocks = match self.reward_end_block {
+                Some(reward_end_block) => blocks.min(reward_end_block.saturating_sub(from)),
+                None => blocks,
+            };
+            if self.accrual_basis != AccrualBasis::Timestamp {
+                return accrued_blocks.into();
+            }
+
+            let millis = self
+                .env()
+                .block_timestamp()
+                .saturating_sub(self.reward_per_weight_timestamp);
+            if accrued_blocks < blocks {
+                // never more than `millis`, so it fits
+                (u128::from(millis) * u128::from(accrued_blocks) / u128::from(blocks)) as u64
+            } else {
+                millis
+            }
+        }
+
+        fn current_reward_per_weight(&self) -> u128 {
+            self.reward_per_weight
+                .saturating_add(self.reward_per_weight_over(self.reward_per_weight_elapsed()))
+        }
+
+        /// Brings `reward_per_weight` up to date. Runs before anything the rate depends on
+        /// changes, so that the time elapsed so far accrues at the previous rate.
+        fn update_reward_per_weight(&mut self) {
+            self.reward_per_weight = self.current_reward_per_weight();
+            self.reward_per_weight_block = self.env().block_number();
+            self.reward_per_weight_timestamp = self.env().block_timestamp();
+        }
+
+        /// Reward the position earned while `reward_per_weight` grew by `reward_per_weight`,
+        /// scaled up by `PRECISION`.
+        fn position_reward(
+            &self,
+            account: AccountId,
+            staking_position: &StakingPosition,
+            reward_per_weight: u128,
+        ) -> Result<U256, StakingError> {
+            // every stake change settles first, so the current stake was held since the checkpoint
+            if staking_position.stake_amount < self.reward_min_stake {
+                return Ok(U256::default());
+            }
+
+            let weighted_stake = match self.weighting {
+                WeightMode::Linear => staking_position.stake_amount,
+                WeightMode::Sqrt => integer_sqrt(staking_position.stake_amount),
+            };
+            // the boost and the rate are in basis points, the rate is already scaled up by
+            // `PRECISION` and the product is taken over 256 bits
+            U256::full_mul(weighted_stake, reward_per_weight)
+                .checked_mul_u128(self.average_boost(account, staking_position))
+                .and_then(|reward| {
+                    reward.div_rem_u128(
+                        BPS_DENOMINATOR * BPS_DENOMINATOR * Balance::from(MILLIS_PER_YEAR),
+                    )
+                })
+                .map(|(reward, _)| reward)
+                .ok_or_else(|| StakingError::Other("reward calculation overflowed".to_owned()))
+        }
+
+        /// Average multiplier over the blocks elapsed since the last checkpoint, taking the
+        /// linear boost decay since the last stake into account.
+        fn average_boost(&self, account: AccountId, staking_position: &StakingPosition) -> Balance {
+            let multiplier = Balance::from(self.get_multiplier(account));
+            let base = Balance::from(DEFAULT_MULTIPLIER_BPS);
+            let decay_blocks = Balance::from(self.boost_decay_blocks);
+            let elapsed = Balance::from(self.elapsed_blocks(staking_position.last_action_block));
+            if multiplier <= base || decay_blocks == 0 || elapsed == 0 {
+                return multiplier;
+            }
+
+            // ages of the position at the checkpoint and now, counted from the last stake
+            let from = Balance::from(
+                staking_position
+                    .last_action_block
+                    .saturating_sub(staking_position.boost_start_block),
+            );
+            let to = from + elapsed;
+            // twice the integral of the decaying share of the boost up to `age`
+            let decayed_area = |age: Balance| {
+                let age = age.min(decay_blocks);
+                2 * decay_blocks * age - age * age
+            };
+
+            let extra = multiplier - base;
+            base + extra * (decayed_area(to) - decayed_area(from)) / (2 * decay_blocks * elapsed)
+        }
+
+        fn unlock_block(staking_position: &StakingPosition) -> BlockNumber {
+            staking_position
+                .lock_start_block
+                .saturating_add(staking_position.lock_period_at_stake)
+        }
+
+        fn is_early_unstake(&self, staking_position: &StakingPosition) -> bool {
+            let current_block = self.env().block_number();
+            if current_block < self.penalty_waiver_until {
+                return false;
+            }
+
+            self.elapsed_blocks(staking_position.first_stake_block) < self.min_stake_duration
+        }
+
+        fn ensure_claim_cooldown_elapsed(&self, account: AccountId) -> Result<(), StakingError> {
+            let last_claim_block = self
+                .stake_positions
+                .get(account)
+                .and_then(|staking_position| staking_position.last_claim_block);
+
+            if let Some(last_claim_block) = last_claim_block {
+                if self.env().block_number() < last_claim_block.saturating_add(self.claim_cooldown)
+                {
+                    return Err(StakingError::ClaimingRewardError(
+                        "claim is still on cooldown".to_owned(),
+                    ));
+                }
+            }
+
+            Ok(())
+        }
+
+        fn ensure_valid_account(account: AccountId) -> Result<(), StakingError> {
+            if account == AccountId::from([0u8; 32]) {
+                return Err(StakingError::Other("invalid account".to_owned()));
+            }
+
+            Ok(())
+        }
+
+        fn ensure_not_contract(&self, account: AccountId) -> Result<(), StakingError> {
+            if account == self.env().account_id() {
+                return Err(StakingError::Other(
+                    "the contract itself cannot hold a stake".to_owned(),
+                ));
+            }
+
+            Ok(())
+        }
+
+        fn ensure_batch_size(size: usize) -> Result<(), StakingError> {
+            if size > MAX_BATCH as usize {
+                return Err(StakingError::Other("batch too large".to_owned()));
+            }
+
+            Ok(())
+        }
+
+        /// Sends `amount` to `to` with the re-entrancy lock held.
+        fn transfer_out(&mut self, to: AccountId, amount: Balance) -> Result<(), StakingError> {
+            self.ensure_not_reentered()?;
+            self.reentrancy_locked = true;
+            let result = self.env().transfer(to, amount);
+            self.reentrancy_locked = false;
+
+            result.map_err(|_| StakingError::Other("transfer failed".to_owned()))
+        }
+
+        fn ensure_not_reentered(&self) -> Result<(), StakingError> {
+            if self.reentrancy_locked {
+                return Err(StakingError::Other("reentrant call".to_owned()));
+            }
+
+            Ok(())
+        }
+
+        fn ensure_not_frozen(&self) -> Result<(), StakingError> {
+            if self.frozen {
+                return Err(StakingError::Other("contract is frozen".to_owned()));
+            }
+
+            Ok(())
+        }
+
+        fn ensure_not_blacklisted(&self, account: AccountId) -> Result<(), StakingError> {
+            if self.is_blacklisted(account) {
+                return Err(StakingError::Other("blacklisted".to_owned()));
+            }
+
+            Ok(())
+        }
+
+        /// Stake only moves between accounts that could claim on it themselves, so that a
+        /// restricted position cannot be carried over to a fresh account.
+        fn ensure_can_move_stake(
+            &self,
+            from: AccountId,
+            to: AccountId,
+        ) -> Result<(), StakingError> {
+            self.ensure_not_frozen()?;
+            for account in [from, to] {
+                self.ensure_not_blacklisted(account)?;
+                if self.is_accrual_frozen(account) {
+                    return Err(StakingError::Other("accrual is frozen".to_owned()));
+                }
+                if self.is_expired(account) {
+                    return Err(StakingError::Other("position is expired".to_owned()));
+                }
+            }
+
+            Ok(())
+        }
+
+        fn ensure_owner(&self) -> Result<(), StakingError> {
+            if !self.is_owner(self.env().caller()) {
+                return Err(StakingError::Other(
+                    "only the owner can perform this action".to_owned(),
+                ));
+            }
+
+            Ok(())
+        }
+
+        /// Blocks since `from`, 0 when `from` lies ahead of the current block, e.g. after a
+        /// rollback. Every block-based accrual goes through here.
+        fn elapsed_blocks(&self, from: BlockNumber) -> BlockNumber {
+            self.env().block_number().saturating_sub(from)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        use ink_lang as ink;
+        use ink_lang::codegen::Env;
 
         use ink_env::{
             test::{default_accounts, get_account_balance, EmittedEvent},
             AccountId,
         };
 
-        type Event = <Staking as ink::reflect::ContractEventBase>::Type;
+        type Event = <Staking as ink::reflect::ContractEventBase>::Type;
+
+        /// By default the off-chain callee is alice, give the contract its own empty account so
+        /// that balance changes of the test accounts can be asserted on.
+        fn set_contract_account() -> AccountId {
+            let contract = AccountId::from([0xff; 32]);
+            ink_env::test::set_callee::<ink_env::DefaultEnvironment>(contract);
+            ink_env::test::set_account_balance::<ink_env::DefaultEnvironment>(contract, 0);
+            contract
+        }
+
+        /// Rewards accrue over a year of `BLOCKS_PER_YEAR` blocks, so test stakes are counted in
+        /// `STAKE_SCALE`: `10 * STAKE_SCALE` at 1000 bps earns one unit per block.
+        const STAKE_SCALE: Balance = BLOCKS_PER_YEAR as Balance;
+
+        /// Gives the default accounts enough balance to stake in `STAKE_SCALE`, even squared for
+        /// the square root weighting.
+        fn fund_default_accounts() {
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            for account in [
+                accounts.alice,
+                accounts.bob,
+                accounts.charlie,
+                accounts.django,
+                accounts.eve,
+                accounts.frank,
+            ] {
+                ink_env::test::set_account_balance::<ink_env::DefaultEnvironment>(
+                    account,
+                    1_000_000 * STAKE_SCALE * STAKE_SCALE,
+                );
+            }
+        }
+
+        /// Runs a payable call that must be rejected by panicking, so that its transfer reverts.
+        fn assert_reverts<F: FnOnce()>(call: F, expected: &str) {
+            let panic = std::panic::catch_unwind(std::panic::AssertUnwindSafe(call))
+                .expect_err("the call should have reverted");
+            let message = panic
+                .downcast_ref::<String>()
+                .map(String::as_str)
+                .or_else(|| panic.downcast_ref::<&str>().copied())
+                .unwrap_or_default();
+            assert!(message.contains(expected), "unexpected panic: {}", message);
+        }
+
+        fn assert_staked_event(
+            event: &EmittedEvent,
+            expected_user: &AccountId,
+            expected_amount: Balance,
+        ) {
+            let decoded_event = <Event as scale::Decode>::decode(&mut &event.data[..])
+                .expect("encountered invalid contract event data buffer");
+            if let Event::Staked(Staked { user, amount }) = decoded_event {
+                assert_eq!(user, *expected_user);
+                assert_eq!(amount, expected_amount);
+            } else {
+                panic!("encountered unexpected event kind: expected a Staked event")
+            }
+        }
+
+        fn assert_unstaked_event(
+            event: &EmittedEvent,
+            expected_user: &AccountId,
+            expected_amount: Balance,
+        ) {
+            let decoded_event = <Event as scale::Decode>::decode(&mut &event.data[..])
+                .expect("encountered invalid contract event data buffer");
+            if let Event::Unstaked(Unstaked { user, amount }) = decoded_event {
+                assert_eq!(user, *expected_user);
+                assert_eq!(amount, expected_amount);
+            } else {
+                panic!("encountered unexpected event kind: expected a Unstaked event")
+            }
+        }
+
+        fn assert_claimed_event(
+            event: &EmittedEvent,
+            expected_user: &AccountId,
+            expected_amount: Balance,
+        ) {
+            let decoded_event = <Event as scale::Decode>::decode(&mut &event.data[..])
+                .expect("encountered invalid contract event data buffer");
+            if let Event::Claimed(Claimed { user, amount }) = decoded_event {
+                assert_eq!(user, *expected_user);
+                assert_eq!(amount, expected_amount);
+            } else {
+                panic!("encountered unexpected event kind: expected a Claimed event")
+            }
+        }
+
+        #[ink::test]
+        fn deployment_works() {
+            let staking = Staking::new(1000);
+            assert_eq!(staking.apy, 1000 * PRECISION);
+            assert_eq!(staking.staked_addresses, Vec::default());
+            assert_eq!(staking.get_total_staked(), 0);
+        }
+
+        #[ink::test]
+        fn is_owner_should_only_be_true_for_deployer() {
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let staking = Staking::new(1000);
+            assert!(staking.is_owner(accounts.alice));
+            assert!(!staking.is_owner(accounts.bob));
+        }
+
+        #[ink::test]
+        fn deployment_with_max_apy_works() {
+            let staking = Staking::new(MAX_APY);
+            assert_eq!(staking.get_apy(), MAX_APY);
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "APY cannot exceed MAX_APY")]
+        fn deployment_above_max_apy_should_not_work() {
+            let _ = Staking::new(MAX_APY + 1);
+        }
+
+        #[ink::test]
+        fn set_apy_should_be_bounded() {
+            fund_default_accounts();
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+
+            for _ in 0..5 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+
+            assert_eq!(staking_contract_instance.set_apy(MAX_APY), Ok(()));
+            assert_eq!(staking_contract_instance.get_apy(), MAX_APY);
+            assert_eq!(
+                staking_contract_instance.set_apy(MAX_APY + 1),
+                Err(StakingError::Other("APY cannot exceed MAX_APY".to_owned()))
+            );
+            assert_eq!(staking_contract_instance.get_apy(), MAX_APY);
+
+            // the 5 blocks before the change are kept at the old rate
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            assert_eq!(staking_contract_instance.rewards_for_user(alice), 5 + 100);
+        }
+
+        #[ink::test]
+        fn apy_reductions_should_be_timelocked() {
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(staking_contract_instance.set_apy_change_delay(5), Ok(()));
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
+
+            assert_eq!(staking_contract_instance.set_apy(500), Ok(()));
+            assert_eq!(staking_contract_instance.get_apy(), 1000);
+            assert_eq!(staking_contract_instance.get_pending_apy(), Some((500, 5)));
+
+            for _ in 0..4 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(
+                staking_contract_instance.apply_pending_apy(),
+                Err(StakingError::Other(
+                    "APY change is still timelocked".to_owned()
+                ))
+            );
+
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(
+                default_accounts::<ink_env::DefaultEnvironment>().bob,
+            );
+            assert_eq!(staking_contract_instance.apply_pending_apy(), Ok(()));
+            assert_eq!(staking_contract_instance.get_apy(), 500);
+            assert_eq!(staking_contract_instance.get_pending_apy(), None);
+            assert_eq!(
+                staking_contract_instance.apply_pending_apy(),
+                Err(StakingError::Other("no pending APY change".to_owned()))
+            );
+
+            // increases apply at once
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+            assert_eq!(staking_contract_instance.set_apy(2000), Ok(()));
+            assert_eq!(staking_contract_instance.get_apy(), 2000);
+            assert_eq!(staking_contract_instance.get_pending_apy(), None);
+        }
+
+        #[ink::test]
+        fn shortening_the_apy_change_delay_should_be_timelocked() {
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(staking_contract_instance.set_apy_change_delay(5), Ok(()));
+            assert_eq!(staking_contract_instance.get_apy_change_delay(), 5);
+
+            // dropping the delay to push a reduction through still waits the current delay
+            assert_eq!(staking_contract_instance.set_apy_change_delay(0), Ok(()));
+            assert_eq!(staking_contract_instance.get_apy_change_delay(), 5);
+            assert_eq!(
+                staking_contract_instance.get_pending_apy_change_delay(),
+                Some((0, 5))
+            );
+            assert_eq!(staking_contract_instance.set_apy(500), Ok(()));
+            assert_eq!(staking_contract_instance.get_apy(), 1000);
+
+            for _ in 0..4 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(
+                staking_contract_instance.apply_pending_apy_change_delay(),
+                Err(StakingError::Other(
+                    "APY change delay change is still timelocked".to_owned()
+                ))
+            );
+
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            assert_eq!(
+                staking_contract_instance.apply_pending_apy_change_delay(),
+                Ok(())
+            );
+            assert_eq!(staking_contract_instance.get_apy_change_delay(), 0);
+            assert_eq!(
+                staking_contract_instance.get_pending_apy_change_delay(),
+                None
+            );
+            assert_eq!(
+                staking_contract_instance.apply_pending_apy_change_delay(),
+                Err(StakingError::Other(
+                    "no pending APY change delay change".to_owned()
+                ))
+            );
+
+            // longer delays apply at once
+            assert_eq!(staking_contract_instance.set_apy_change_delay(10), Ok(()));
+            assert_eq!(staking_contract_instance.get_apy_change_delay(), 10);
+        }
+
+        #[ink::test]
+        fn average_apy_paid_should_match_the_realized_yield() {
+            fund_default_accounts();
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 1000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+
+            // 10 staked for 10 blocks, then 20 for 10 blocks, at 10% a year
+            assert_eq!(staking_contract_instance.claim_reward(), Ok(()));
+            assert_eq!(staking_contract_instance.get_total_rewards_paid(), 30);
+            assert_eq!(staking_contract_instance.average_apy_paid(), 1000);
+
+            // idle blocks with the same TVL dilute the realized rate
+            for _ in 0..30 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.average_apy_paid(), 333);
+        }
+
+        #[ink::test]
+        fn unstake_detailed_should_report_principal_and_reward() {
+            fund_default_accounts();
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+            set_contract_account();
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+
+            let balance_before =
+                get_account_balance::<ink_env::DefaultEnvironment>(accounts.alice).unwrap();
+            let (principal, reward) = staking_contract_instance
+                .unstake_detailed(4 * STAKE_SCALE)
+                .unwrap();
+            assert_eq!((principal, reward), (4 * STAKE_SCALE, 10));
+            assert_eq!(
+                get_account_balance::<ink_env::DefaultEnvironment>(accounts.alice).unwrap(),
+                balance_before + principal + reward
+            );
+            assert_eq!(
+                staking_contract_instance.get_account_stake(accounts.alice),
+                6 * STAKE_SCALE
+            );
+        }
+
+        #[ink::test]
+        fn idle_positions_should_expire() {
+            fund_default_accounts();
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(
+                staking_contract_instance.expire_idle(alice),
+                Err(StakingError::Other("idle expiry is disabled".to_owned()))
+            );
+            assert_eq!(
+                staking_contract_instance.set_idle_expiry_blocks(Some(10)),
+                Ok(())
+            );
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+
+            for _ in 0..9 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(
+                staking_contract_instance.expire_idle(alice),
+                Err(StakingError::Other("position is not idle".to_owned()))
+            );
+
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            let bob = default_accounts::<ink_env::DefaultEnvironment>().bob;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(bob);
+            assert_eq!(
+                staking_contract_instance.expire_idle(alice),
+                Err(StakingError::Other(
+                    "only the owner can perform this action".to_owned()
+                ))
+            );
+            assert!(!staking_contract_instance.is_expired(alice));
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+            assert_eq!(staking_contract_instance.expire_idle(alice), Ok(()));
+            assert!(staking_contract_instance.is_expired(alice));
+            assert_eq!(staking_contract_instance.rewards_for_user(alice), 10);
+
+            // an expired position keeps its rewards but stops accruing
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.rewards_for_user(alice), 10);
+            assert_eq!(
+                staking_contract_instance.expire_idle(alice),
+                Err(StakingError::Other("position already expired".to_owned()))
+            );
+        }
+
+        #[ink::test]
+        fn a_second_claim_in_the_same_block_should_pay_nothing() {
+            fund_default_accounts();
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+            set_contract_account();
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+
+            assert_eq!(staking_contract_instance.claim_reward(), Ok(()));
+            assert_eq!(staking_contract_instance.get_total_rewards_paid(), 10);
+
+            let balance_before =
+                get_account_balance::<ink_env::DefaultEnvironment>(accounts.alice).unwrap();
+            assert_eq!(
+                staking_contract_instance.rewards_for_user(accounts.alice),
+                0
+            );
+            assert_eq!(staking_contract_instance.claim_reward(), Ok(()));
+            assert_eq!(
+                get_account_balance::<ink_env::DefaultEnvironment>(accounts.alice).unwrap(),
+                balance_before
+            );
+            assert_eq!(staking_contract_instance.get_total_rewards_paid(), 10);
+        }
+
+        #[ink::test]
+        fn calls_during_a_transfer_should_be_rejected() {
+            fund_default_accounts();
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            for _ in 0..5 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+
+            // what a call re-entering while a transfer is in flight sees
+            staking_contract_instance.reentrancy_locked = true;
+            let reentered = Err(StakingError::Other("reentrant call".to_owned()));
+            assert_eq!(staking_contract_instance.claim_reward(), reentered);
+            assert_eq!(
+                staking_contract_instance.claim_up_to_then_compound(1),
+                reentered
+            );
+            assert_eq!(
+                staking_contract_instance.unstake(10 * STAKE_SCALE),
+                reentered
+            );
+            assert_eq!(staking_contract_instance.rewards_for_user(alice), 5);
+            assert_eq!(
+                staking_contract_instance.get_account_stake(alice),
+                10 * STAKE_SCALE
+            );
+
+            staking_contract_instance.reentrancy_locked = false;
+            assert_eq!(staking_contract_instance.claim_reward(), Ok(()));
+            assert!(!staking_contract_instance.reentrancy_locked);
+        }
+
+        #[ink::test]
+        fn total_pending_rewards_should_sum_all_positions() {
+            fund_default_accounts();
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(staking_contract_instance.total_pending_rewards(), 0);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 20 * STAKE_SCALE);
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+
+            assert_eq!(
+                staking_contract_instance.total_pending_rewards(),
+                staking_contract_instance.rewards_for_user(accounts.alice)
+                    + staking_contract_instance.rewards_for_user(accounts.bob)
+            );
+            assert_eq!(staking_contract_instance.total_pending_rewards(), 30);
+        }
+
+        #[ink::test]
+        fn blacklisted_accounts_should_only_be_able_to_unstake() {
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
+            assert_eq!(
+                staking_contract_instance.blacklist(accounts.bob),
+                Err(StakingError::Other(
+                    "only the owner can perform this action".to_owned()
+                ))
+            );
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+            assert_eq!(staking_contract_instance.blacklist(accounts.bob), Ok(()));
+            assert!(staking_contract_instance.is_blacklisted(accounts.bob));
+
+            let blacklisted = Err(StakingError::Other("blacklisted".to_owned()));
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            assert_reverts(
+                || {
+                    let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
+                },
+                "blacklisted",
+            );
+            assert_eq!(staking_contract_instance.claim_reward(), blacklisted);
+            assert_eq!(staking_contract_instance.compound(), blacklisted);
+            assert_eq!(staking_contract_instance.unstake(10), Ok(()));
+            assert_eq!(staking_contract_instance.get_account_stake(accounts.bob), 0);
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+            assert_eq!(staking_contract_instance.unblacklist(accounts.bob), Ok(()));
+            assert!(!staking_contract_instance.is_blacklisted(accounts.bob));
+        }
+
+        #[ink::test]
+        fn apy_should_diminish_above_the_target_tvl() {
+            fund_default_accounts();
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(
+                staking_contract_instance.set_target_tvl(100 * STAKE_SCALE),
+                Ok(())
+            );
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 100 * STAKE_SCALE);
+            assert_eq!(staking_contract_instance.effective_apy(), 1000);
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(
+                staking_contract_instance.rewards_for_user(accounts.alice),
+                100
+            );
+
+            // doubling the TVL beyond the target halves the rate
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 100 * STAKE_SCALE);
+            assert_eq!(staking_contract_instance.effective_apy(), 500);
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.rewards_for_user(accounts.bob), 50);
+        }
+
+        #[ink::test]
+        fn pool_runway_should_match_the_emission() {
+            fund_default_accounts();
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 1000);
+            assert_eq!(staking_contract_instance.pool_runway_blocks(), None);
+
+            // 10 per block
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 100 * STAKE_SCALE);
+            assert_eq!(staking_contract_instance.pool_runway_blocks(), Some(100));
+
+            // 15 per block
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 50 * STAKE_SCALE);
+            assert_eq!(staking_contract_instance.pool_runway_blocks(), Some(66));
+        }
+
+        #[ink::test]
+        fn restricted_positions_should_not_move_to_other_accounts() {
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+            assert_eq!(staking_contract_instance.blacklist(accounts.bob), Ok(()));
+            assert_eq!(
+                staking_contract_instance.freeze_accrual(accounts.bob),
+                Ok(())
+            );
+
+            let blacklisted = Err(StakingError::Other("blacklisted".to_owned()));
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(
+                staking_contract_instance.migrate_position(accounts.eve),
+                blacklisted
+            );
+            assert_eq!(
+                staking_contract_instance.transfer_stake(accounts.eve, 5),
+                blacklisted
+            );
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+            assert_eq!(staking_contract_instance.unblacklist(accounts.bob), Ok(()));
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(
+                staking_contract_instance.migrate_position(accounts.eve),
+                Err(StakingError::Other("accrual is frozen".to_owned()))
+            );
+
+            // neither can a position be handed to a blacklisted account
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+            assert_eq!(
+                staking_contract_instance.unfreeze_accrual(accounts.bob),
+                Ok(())
+            );
+            assert_eq!(staking_contract_instance.blacklist(accounts.eve), Ok(()));
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(
+                staking_contract_instance.transfer_stake(accounts.eve, 5),
+                blacklisted
+            );
+            assert_eq!(
+                staking_contract_instance.get_account_stake(accounts.bob),
+                10
+            );
+            assert_eq!(staking_contract_instance.get_account_stake(accounts.eve), 0);
+        }
+
+        #[ink::test]
+        fn emergency_pause_should_only_allow_unstaking() {
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+
+            assert_eq!(staking_contract_instance.emergency_pause(), Ok(()));
+            assert!(staking_contract_instance.is_frozen());
+
+            let frozen = Err(StakingError::Other("contract is frozen".to_owned()));
+            assert_reverts(
+                || {
+                    let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
+                },
+                "contract is frozen",
+            );
+            assert_eq!(staking_contract_instance.claim_reward(), frozen);
+            assert_eq!(staking_contract_instance.compound(), frozen);
+            assert_eq!(staking_contract_instance.unstake(10), Ok(()));
+            assert_eq!(staking_contract_instance.get_account_stake(alice), 0);
+
+            assert_eq!(staking_contract_instance.emergency_unpause(), Ok(()));
+            assert!(!staking_contract_instance.is_frozen());
+        }
+
+        #[ink::test]
+        fn top_up_should_only_move_the_last_action_block() {
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(staking_contract_instance.staker_blocks(alice), None);
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
+            assert_eq!(staking_contract_instance.staker_blocks(alice), Some((1, 1)));
+
+            for _ in 0..5 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
+            assert_eq!(staking_contract_instance.staker_blocks(alice), Some((1, 6)));
+        }
+
+        #[ink::test]
+        fn time_staked_should_count_seconds_since_the_first_stake() {
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(
+                staking_contract_instance.set_accrual_basis(AccrualBasis::Timestamp),
+                Ok(())
+            );
+            assert_eq!(staking_contract_instance.time_staked_seconds(alice), 0);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
+
+            // the off-chain environment has no `set_block_timestamp`, every block adds 6 ms
+            for _ in 0..2_000 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
+            assert_eq!(staking_contract_instance.time_staked_seconds(alice), 12);
+        }
+
+        #[ink::test]
+        fn a_checkpoint_ahead_of_the_current_block_should_accrue_nothing() {
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(
+                staking_contract_instance.set_multiplier(alice, 20_000),
+                Ok(())
+            );
+            assert_eq!(staking_contract_instance.set_boost_decay_blocks(10), Ok(()));
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+
+            // as if the chain rolled back to before the checkpoint
+            let staking_position = staking_contract_instance
+                .stake_positions
+                .get(alice)
+                .unwrap();
+            staking_contract_instance.stake_positions.insert(
+                alice,
+                &StakingPosition {
+                    last_action_block: 10,
+                    lock_start_block: 10,
+                    boost_start_block: 10,
+                    ..staking_position
+                },
+            );
+            for _ in 0..5 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+
+            assert_eq!(staking_contract_instance.rewards_for_user(alice), 0);
+            assert_eq!(
+                staking_contract_instance.rewards_for_accounts(vec![alice]),
+                Ok(vec![0])
+            );
+            assert_eq!(staking_contract_instance.total_pending_rewards(), 0);
+            assert_eq!(staking_contract_instance.current_boost(alice), 20_000);
+            assert_eq!(staking_contract_instance.claim_reward(), Ok(()));
+            assert_eq!(staking_contract_instance.get_total_rewards_paid(), 0);
+            assert_eq!(staking_contract_instance.compound(), Ok(()));
+            assert_eq!(staking_contract_instance.get_account_stake(alice), 10);
+        }
+
+        #[ink::test]
+        fn claimed_rewards_should_vest_linearly() {
+            fund_default_accounts();
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+            set_contract_account();
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(
+                staking_contract_instance.set_reward_vesting_blocks(10),
+                Ok(())
+            );
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 100 * STAKE_SCALE);
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+
+            let alice_balance =
+                || get_account_balance::<ink_env::DefaultEnvironment>(accounts.alice).unwrap();
+            let balance_before = alice_balance();
+            assert_eq!(staking_contract_instance.claim_reward(), Ok(()));
+            assert_eq!(alice_balance(), balance_before);
+            assert_eq!(
+                staking_contract_instance.get_vesting(accounts.alice),
+                Some(VestingSchedule {
+                    amount: 100,
+                    released: 0,
+                    start_block: 10,
+                    duration: 10,
+                })
+            );
+            assert_eq!(staking_contract_instance.get_reward_pool(), 0);
+            assert_eq!(staking_contract_instance.get_total_vesting(), 100);
+
+            // partial release
+            for _ in 0..4 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.claim_vested(), Ok(()));
+            assert_eq!(alice_balance(), balance_before + 40);
+            assert_eq!(
+                staking_contract_instance
+                    .get_vesting(accounts.alice)
+                    .map(|schedule| schedule.released),
+                Some(40)
+            );
+
+            // full release
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.claim_vested(), Ok(()));
+            assert_eq!(alice_balance(), balance_before + 100);
+            assert_eq!(staking_contract_instance.get_vesting(accounts.alice), None);
+            assert_eq!(staking_contract_instance.get_total_vesting(), 0);
+        }
+
+        #[ink::test]
+        fn every_payout_should_vest() {
+            fund_default_accounts();
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+            set_contract_account();
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(
+                staking_contract_instance.set_reward_vesting_blocks(10),
+                Ok(())
+            );
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+
+            let balance =
+                |account| get_account_balance::<ink_env::DefaultEnvironment>(account).unwrap();
+            let bob_balance = balance(accounts.bob);
+            assert_eq!(staking_contract_instance.claim_to(accounts.bob), Ok(()));
+            assert_eq!(balance(accounts.bob), bob_balance);
+            assert_eq!(staking_contract_instance.get_total_vesting(), 10);
+
+            // the unstake returns the principal, its reward vests
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            let alice_balance = balance(accounts.alice);
+            assert_eq!(staking_contract_instance.unstake(10 * STAKE_SCALE), Ok(()));
+            // 10 from the principal and the 10 that vested from the first claim
+            assert_eq!(
+                balance(accounts.alice),
+                alice_balance + 10 * STAKE_SCALE + 10
+            );
+            assert_eq!(staking_contract_instance.get_total_vesting(), 10);
+            assert_eq!(
+                staking_contract_instance
+                    .get_vesting(accounts.alice)
+                    .map(|schedule| schedule.amount),
+                Some(10)
+            );
+        }
+
+        #[ink::test]
+        fn observer_should_be_owner_only() {
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            // the off-chain environment cannot invoke contracts, so only the configuration is
+            // covered here
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(
+                staking_contract_instance.set_observer(Some(accounts.django)),
+                Ok(())
+            );
+            assert_eq!(
+                staking_contract_instance.get_observer(),
+                Some(accounts.django)
+            );
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(
+                staking_contract_instance.set_observer(None),
+                Err(StakingError::Other(
+                    "only the owner can perform this action".to_owned()
+                ))
+            );
+        }
+
+        #[ink::test]
+        fn contract_balance_should_match_the_accounting() {
+            fund_default_accounts();
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+            set_contract_account();
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let assert_reconciled = |staking: &Staking| {
+                assert_eq!(
+                    staking.get_contract_balance(),
+                    staking.get_total_staked()
+                        + staking.get_reward_pool()
+                        + staking.get_total_vesting()
+                );
+            };
+
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 500);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 100 * STAKE_SCALE);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 50 * STAKE_SCALE);
+            assert_reconciled(&staking_contract_instance);
+
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.claim_reward(), Ok(()));
+            assert_reconciled(&staking_contract_instance);
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+            assert_eq!(
+                staking_contract_instance.set_reward_vesting_blocks(10),
+                Ok(())
+            );
+            assert_eq!(staking_contract_instance.claim_reward(), Ok(()));
+            assert_eq!(staking_contract_instance.unstake(40 * STAKE_SCALE), Ok(()));
+            assert_reconciled(&staking_contract_instance);
+            assert!(staking_contract_instance.get_total_vesting() > 0);
+        }
+
+        #[ink::test]
+        fn set_treasury_should_reject_the_zero_account() {
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(
+                staking_contract_instance.set_treasury(AccountId::from([0u8; 32])),
+                Err(StakingError::Other("invalid account".to_owned()))
+            );
+            assert_eq!(staking_contract_instance.get_treasury(), None);
+            assert_eq!(ink_env::test::recorded_events().count(), 0);
+
+            assert_eq!(
+                staking_contract_instance.set_treasury(accounts.django),
+                Ok(())
+            );
+            assert_eq!(
+                staking_contract_instance.get_treasury(),
+                Some(accounts.django)
+            );
+
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            assert_eq!(emitted_events.len(), 1);
+            let decoded_event = <Event as scale::Decode>::decode(&mut &emitted_events[0].data[..])
+                .expect("encountered invalid contract event data buffer");
+            if let Event::TreasuryUpdated(TreasuryUpdated { treasury }) = decoded_event {
+                assert_eq!(treasury, accounts.django);
+            } else {
+                panic!("encountered unexpected event kind: expected a TreasuryUpdated event")
+            }
+        }
+
+        #[ink::test]
+        fn treasury_should_only_be_pulled_from_on_a_shortfall() {
+            fund_default_accounts();
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(
+                staking_contract_instance.set_treasury(accounts.django),
+                Ok(())
+            );
+            assert_eq!(
+                staking_contract_instance.get_treasury(),
+                Some(accounts.django)
+            );
+
+            // the off-chain environment cannot invoke contracts, a covered claim must not try to
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.claim_reward(), Ok(()));
+            assert_eq!(staking_contract_instance.get_reward_pool(), 90);
+        }
+
+        #[ink::test]
+        fn active_staker_count_should_skip_zero_positions() {
+            fund_default_accounts();
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+            set_contract_account();
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+
+            // bob's position lingers with its unclaimed reward
+            assert_eq!(
+                staking_contract_instance.unstake_no_claim(10 * STAKE_SCALE),
+                Ok(())
+            );
+            assert_eq!(staking_contract_instance.get_staker_count(), 2);
+            assert_eq!(staking_contract_instance.active_staker_count(), 1);
+        }
+
+        #[ink::test]
+        fn unstake_no_claim_should_leave_the_reward_claimable() {
+            fund_default_accounts();
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+            set_contract_account();
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+
+            let alice_balance =
+                || get_account_balance::<ink_env::DefaultEnvironment>(accounts.alice).unwrap();
+            let balance_before = alice_balance();
+            assert_eq!(
+                staking_contract_instance.unstake_no_claim(10 * STAKE_SCALE),
+                Ok(())
+            );
+            assert_eq!(alice_balance(), balance_before + 10 * STAKE_SCALE);
+            assert_eq!(
+                staking_contract_instance.get_account_stake(accounts.alice),
+                0
+            );
+            assert_eq!(staking_contract_instance.get_total_staked(), 0);
+
+            // nothing accrues on the empty position, the leftover reward stays claimable
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(
+                staking_contract_instance.rewards_for_user(accounts.alice),
+                10
+            );
+            assert_eq!(staking_contract_instance.claim_reward(), Ok(()));
+            assert_eq!(alice_balance(), balance_before + 10 * STAKE_SCALE + 10);
+            assert_eq!(staking_contract_instance.get_staker_count(), 0);
+            assert_eq!(
+                staking_contract_instance.staker_blocks(accounts.alice),
+                None
+            );
+        }
+
+        #[ink::test]
+        fn switching_weighting_should_keep_the_accrued_rewards() {
+            fund_default_accounts();
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let stake = 100 * STAKE_SCALE * STAKE_SCALE;
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), stake);
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(
+                staking_contract_instance.rewards_for_user(alice),
+                100 * STAKE_SCALE
+            );
+
+            assert_eq!(
+                staking_contract_instance.set_weighting(WeightMode::Sqrt),
+                Ok(())
+            );
+            assert_eq!(staking_contract_instance.get_weighting(), WeightMode::Sqrt);
+            assert_eq!(
+                staking_contract_instance.rewards_for_user(alice),
+                100 * STAKE_SCALE
+            );
+
+            // the square root, 10 * STAKE_SCALE, now earns 1 per block
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(
+                staking_contract_instance.rewards_for_user(alice),
+                100 * STAKE_SCALE + 10
+            );
+        }
+
+        #[ink::test]
+        fn total_claimed_by_should_sum_all_claims() {
+            fund_default_accounts();
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            assert_eq!(staking_contract_instance.total_claimed_by(alice), 0);
+
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.claim_reward(), Ok(()));
+            for _ in 0..5 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.claim_reward(), Ok(()));
+            assert_eq!(staking_contract_instance.total_claimed_by(alice), 10 + 5);
+
+            // kept after the position is gone
+            assert_eq!(staking_contract_instance.unstake(10 * STAKE_SCALE), Ok(()));
+            assert_eq!(staking_contract_instance.total_claimed_by(alice), 15);
+        }
+
+        #[ink::test]
+        fn staking_into_an_insolvent_contract_should_not_work() {
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+            let contract = set_contract_account();
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 100);
+            assert_eq!(staking_contract_instance.get_account_stake(alice), 100);
+
+            ink_env::test::set_account_balance::<ink_env::DefaultEnvironment>(contract, 50);
+            assert_reverts(
+                || {
+                    let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
+                },
+                "contract insolvent",
+            );
+            assert_eq!(staking_contract_instance.get_account_stake(alice), 100);
+        }
+
+        #[ink::test]
+        fn rewards_at_block_should_interpolate_the_history() {
+            fund_default_accounts();
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.claim_reward(), Ok(()));
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+
+            // 1 per block, claimed at block 10
+            assert_eq!(staking_contract_instance.rewards_at_block(alice, 5), 5);
+            assert_eq!(staking_contract_instance.rewards_at_block(alice, 10), 0);
+            assert_eq!(staking_contract_instance.rewards_at_block(alice, 15), 5);
+            assert_eq!(staking_contract_instance.rewards_at_block(alice, 20), 10);
+            assert_eq!(
+                staking_contract_instance
+                    .rewards_at_block(default_accounts::<ink_env::DefaultEnvironment>().bob, 5),
+                0
+            );
+        }
+
+        #[ink::test]
+        fn raising_min_stake_should_grandfather_small_positions() {
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
+            assert_eq!(staking_contract_instance.set_min_stake(50), Ok(()));
+
+            // the existing position stays, but cannot shrink or top up below the minimum
+            assert_eq!(staking_contract_instance.get_account_stake(alice), 10);
+            assert_eq!(
+                staking_contract_instance.unstake(5),
+                Err(StakingError::UnstakeError(
+                    "remaining stake would be below the minimum stake".to_owned()
+                ))
+            );
+            assert_reverts(
+                || {
+                    let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 20);
+                },
+                "stake below the minimum stake",
+            );
+
+            // a full exit is always possible
+            assert_eq!(staking_contract_instance.unstake(10), Ok(()));
+            assert_eq!(staking_contract_instance.get_account_stake(alice), 0);
+        }
+
+        #[ink::test]
+        fn rewards_for_accounts_should_follow_the_input_order() {
+            fund_default_accounts();
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 20 * STAKE_SCALE);
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+
+            assert_eq!(
+                staking_contract_instance.rewards_for_accounts(vec![
+                    accounts.bob,
+                    accounts.charlie,
+                    accounts.alice
+                ]),
+                Ok(vec![20, 0, 10])
+            );
+            assert_eq!(
+                staking_contract_instance
+                    .rewards_for_accounts(vec![accounts.alice; MAX_BATCH as usize + 1]),
+                Err(StakingError::Other("batch too large".to_owned()))
+            );
+        }
+
+        #[ink::test]
+        fn a_tvl_change_should_not_reprice_accrued_rewards() {
+            fund_default_accounts();
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(
+                staking_contract_instance.set_target_tvl(10 * STAKE_SCALE),
+                Ok(())
+            );
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(
+                staking_contract_instance.rewards_for_user(accounts.alice),
+                10
+            );
+
+            // bob doubles the TVL, which halves the rate from now on only
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            assert_eq!(
+                staking_contract_instance.rewards_for_user(accounts.alice),
+                10
+            );
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(
+                staking_contract_instance.rewards_for_user(accounts.alice),
+                15
+            );
+            // without moving the checkpoint of alice
+            assert_eq!(
+                staking_contract_instance
+                    .stake_positions
+                    .get(accounts.alice)
+                    .unwrap()
+                    .last_action_block,
+                0
+            );
+
+            // a new emission cap applies from now on as well
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+            assert_eq!(
+                staking_contract_instance.set_max_emission_per_block(Some(0)),
+                Ok(())
+            );
+            assert_eq!(
+                staking_contract_instance.rewards_for_user(accounts.alice),
+                15
+            );
+        }
+
+        #[ink::test]
+        fn effective_apy_should_not_drop_below_the_floor() {
+            fund_default_accounts();
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(
+                staking_contract_instance.set_apy_bounds(600, 500),
+                Err(StakingError::Other(
+                    "APY floor cannot exceed the APY ceiling".to_owned()
+                ))
+            );
+            assert_eq!(staking_contract_instance.set_apy_bounds(600, 1000), Ok(()));
+            assert_eq!(
+                staking_contract_instance.set_target_tvl(100 * STAKE_SCALE),
+                Ok(())
+            );
+
+            // twice the target TVL would halve the rate to 500
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 200 * STAKE_SCALE);
+            assert_eq!(staking_contract_instance.effective_apy(), 600);
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.rewards_for_user(alice), 120);
+        }
+
+        #[ink::test]
+        fn applying_a_config_should_reproduce_the_source() {
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            // the off-chain environment cannot invoke contracts, so `new_cloned` is covered
+            // through the config it reads and applies
+            let mut source = Staking::new_with_weighting(1500, WeightMode::Sqrt);
+            assert_eq!(source.set_lock_period(100), Ok(()));
+            assert_eq!(source.set_keeper_fee_bps(250), Ok(()));
+            assert_eq!(source.set_max_unstake_per_tx(Some(1000)), Ok(()));
+            assert_eq!(source.set_min_stake(10), Ok(()));
+            assert_eq!(source.set_target_tvl(5000), Ok(()));
+            assert_eq!(source.set_apy_bounds(100, 2000), Ok(()));
+            assert_eq!(source.set_reward_end_block(Some(1000)), Ok(()));
+            assert_eq!(source.set_reward_vesting_blocks(50), Ok(()));
+            assert_eq!(source.set_apy_change_delay(20), Ok(()));
+            assert_eq!(source.set_referral_bonus(5, 10, 30), Ok(()));
+            assert_eq!(source.set_epoch_length(40), Ok(()));
+            let config = source.get_config();
+            assert_eq!(config.apy, 1500 * PRECISION);
+            assert_eq!(config.weighting, WeightMode::Sqrt);
+            assert_eq!(config.lock_period, 100);
+            assert_eq!(config.keeper_fee_bps, 250);
+            assert_eq!(config.target_tvl, 5000);
+            assert_eq!((config.apy_floor, config.apy_ceiling), (100, 2000));
+            assert_eq!(config.reward_end_block, Some(1000));
+            assert_eq!(config.reward_vesting_blocks, 50);
+            assert_eq!(config.apy_change_delay, 20);
+            assert_eq!(
+                (
+                    config.referral_bonus,
+                    config.referral_min_stake,
+                    config.referral_lock_blocks
+                ),
+                (5, 10, 30)
+            );
+            assert_eq!(config.epoch_length, 40);
+
+            let mut clone = Staking::new(1000);
+            clone.apply_config(config.clone());
+            assert_eq!(clone.get_config(), config);
+            assert_eq!(clone.get_total_staked(), 0);
+        }
+
+        #[ink::test]
+        fn freezing_an_account_should_not_affect_others() {
+            fund_default_accounts();
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            for _ in 0..5 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+            assert_eq!(
+                staking_contract_instance.freeze_accrual(accounts.bob),
+                Ok(())
+            );
+            assert!(staking_contract_instance.is_accrual_frozen(accounts.bob));
+            for _ in 0..5 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(
+                staking_contract_instance.rewards_for_user(accounts.alice),
+                10
+            );
+            assert_eq!(staking_contract_instance.rewards_for_user(accounts.bob), 5);
+
+            // the frozen blocks are not accrued after unfreezing
+            assert_eq!(
+                staking_contract_instance.unfreeze_accrual(accounts.bob),
+                Ok(())
+            );
+            for _ in 0..5 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(
+                staking_contract_instance.rewards_for_user(accounts.alice),
+                15
+            );
+            assert_eq!(staking_contract_instance.rewards_for_user(accounts.bob), 10);
+        }
+
+        #[ink::test]
+        fn unstake_should_not_underflow_total_staked() {
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
+
+            // a position larger than the recorded total can only come from a bug
+            staking_contract_instance.total_staked = 5;
+            assert_eq!(
+                staking_contract_instance.unstake(10),
+                Err(StakingError::Other("total_staked underflow".to_owned()))
+            );
+            assert_eq!(staking_contract_instance.get_account_stake(alice), 10);
+            assert_eq!(staking_contract_instance.get_total_staked(), 5);
+        }
+
+        #[ink::test]
+        fn pool_utilization_should_be_the_owed_share_of_the_pool() {
+            fund_default_accounts();
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.pool_utilization_bps(), 0);
+
+            // 10 owed out of 40
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 40);
+            assert_eq!(staking_contract_instance.pool_utilization_bps(), 2500);
+
+            // 50 owed out of 40
+            for _ in 0..40 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.pool_utilization_bps(), 10_000);
+        }
+
+        #[ink::test]
+        fn crossing_an_epoch_boundary_should_emit_the_epoch_rewards() {
+            fund_default_accounts();
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(staking_contract_instance.set_epoch_length(10), Ok(()));
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+
+            for _ in 0..5 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.claim_reward(), Ok(()));
+            for _ in 0..7 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.claim_reward(), Ok(()));
+            assert_eq!(staking_contract_instance.get_current_epoch(), 1);
+            assert_eq!(staking_contract_instance.get_total_rewards_paid(), 12);
+
+            let epoch_events = ink_env::test::recorded_events()
+                .filter_map(|event| {
+                    match <Event as scale::Decode>::decode(&mut &event.data[..])
+                        .expect("encountered invalid contract event data buffer")
+                    {
+                        Event::EpochRewardsDistributed(EpochRewardsDistributed {
+                            epoch,
+                            total,
+                        }) => Some((epoch, total)),
+                        _ => None,
+                    }
+                })
+                .collect::<Vec<_>>();
+            // only the 5 paid before block 10 belong to epoch 0
+            assert_eq!(epoch_events, vec![(0, 5)]);
+        }
+
+        #[ink::test]
+        fn upgrade_should_be_owner_only() {
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking = Staking::new(1000);
+
+            // the off-chain environment cannot swap code, only the guard is covered here
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(
+                staking.upgrade([1u8; 32]),
+                Err(StakingError::Other(
+                    "only the owner can perform this action".to_owned()
+                ))
+            );
+            assert_eq!(ink_env::test::recorded_events().count(), 0);
+        }
+
+        #[ink::test]
+        fn ownership_transfer_should_need_acceptance() {
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(
+                staking_contract_instance.transfer_ownership(accounts.bob),
+                Ok(())
+            );
+            assert_eq!(
+                staking_contract_instance.get_pending_owner(),
+                Some(accounts.bob)
+            );
+            assert!(staking_contract_instance.is_owner(accounts.alice));
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.charlie);
+            assert_eq!(
+                staking_contract_instance.accept_ownership(),
+                Err(StakingError::Other(
+                    "only the pending owner can accept ownership".to_owned()
+                ))
+            );
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(staking_contract_instance.accept_ownership(), Ok(()));
+            assert!(staking_contract_instance.is_owner(accounts.bob));
+            assert!(!staking_contract_instance.is_owner(accounts.alice));
+            assert_eq!(staking_contract_instance.get_pending_owner(), None);
+        }
+
+        #[ink::test]
+        fn set_owner_should_change_the_owner_at_once() {
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            if !cfg!(feature = "testing") {
+                assert_eq!(
+                    staking_contract_instance.set_owner(accounts.bob),
+                    Err(StakingError::Other(
+                        "set_owner is only available in testing builds".to_owned()
+                    ))
+                );
+                assert!(staking_contract_instance.is_owner(accounts.alice));
+            } else {
+                assert_eq!(staking_contract_instance.set_owner(accounts.bob), Ok(()));
+                assert!(staking_contract_instance.is_owner(accounts.bob));
+                assert_eq!(
+                    staking_contract_instance.set_owner(accounts.alice),
+                    Err(StakingError::Other(
+                        "only the owner can perform this action".to_owned()
+                    ))
+                );
+            }
+        }
+
+        fn assert_defaults(
+            staking: &Staking,
+            apy: u64,
+            weighting: WeightMode,
+            decimals: u8,
+            skip_zero_claims: bool,
+        ) {
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            assert!(staking.is_owner(accounts.alice));
+            assert_eq!(staking.get_pending_owner(), None);
+            assert!(!staking.is_paused());
+            assert!(!staking.is_frozen());
+            assert!(!staking.reentrancy_locked);
+            assert_eq!(staking.get_reward_vesting_blocks(), 0);
+            assert_eq!(staking.get_total_vesting(), 0);
+            assert_eq!(staking.get_observer(), None);
+            assert_eq!(staking.get_treasury(), None);
+            assert_eq!(staking.get_epoch_length(), 0);
+            assert_eq!(staking.get_current_epoch(), 0);
+            assert_eq!(staking.get_skip_zero_claims(), skip_zero_claims);
+            assert_eq!(staking.get_decimals(), decimals);
+            assert_eq!(
+                staking.reward_rate_info(),
+                (u128::from(apy) * PRECISION, BLOCKS_PER_YEAR, weighting)
+            );
+            assert_eq!(staking.get_accrual_basis(), AccrualBasis::Blocks);
+            assert_eq!(staking.get_reward_pool(), 0);
+            assert_eq!(staking.get_total_rewards_paid(), 0);
+            assert_eq!(staking.get_max_emission_per_block(), None);
+            // without boosts or thresholds every staked unit weighs one
+            assert_eq!(staking.total_reward_weight, staking.get_total_staked());
+            assert_eq!(staking.reward_per_weight, 0);
+            assert_eq!(staking.reward_per_weight_block, 0);
+            assert_eq!(staking.reward_per_weight_timestamp, 0);
+            assert!(!staking.is_rewards_frozen());
+            assert_eq!(staking.get_early_unstake_penalty(), (0, 0));
+            assert_eq!(staking.get_lock_period(), 0);
+            assert_eq!(staking.get_keeper_fee_bps(), 0);
+            assert_eq!(staking.get_claim_cooldown(), 0);
+            assert_eq!(staking.get_reward_min_stake(), 0);
+            assert_eq!(staking.get_min_stake(), 0);
+            assert_eq!(staking.get_reward_burn(), (0, AccountId::from([0u8; 32])));
+            assert_eq!(staking.get_activity_counts(), (0, 0, 0));
+            assert_eq!(staking.get_reward_end_block(), None);
+            assert_eq!(staking.get_compound_frequency(), 0);
+            assert_eq!(staking.get_max_unstake_per_tx(), None);
+            assert_eq!(staking.get_max_reward_multiple_bps(), None);
+            assert!(!staking.is_acknowledgement_required());
+            assert_eq!(staking.get_referral_bonus(), 0);
+            assert_eq!(staking.get_referral_requirements(), (0, 0));
+            assert_eq!(staking.get_apy_change_delay(), 0);
+            assert_eq!(staking.get_pending_apy(), None);
+            assert_eq!(staking.get_pending_apy_change_delay(), None);
+            assert_eq!(staking.get_deploy_block(), 0);
+            assert_eq!(staking.get_idle_expiry_blocks(), None);
+            assert_eq!(staking.get_target_tvl(), 0);
+            assert_eq!(staking.get_apy_bounds(), (0, MAX_APY));
+            assert_eq!(staking.average_apy_paid(), 0);
+            assert_eq!(
+                staking.get_multiplier(accounts.alice),
+                DEFAULT_MULTIPLIER_BPS
+            );
+        }
+
+        #[ink::test]
+        fn every_constructor_should_set_the_defaults() {
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            assert_defaults(
+                &Staking::new(1000),
+                1000,
+                WeightMode::Linear,
+                DEFAULT_DECIMALS,
+                false,
+            );
+            assert_defaults(
+                &Staking::new_with_weighting(500, WeightMode::Sqrt),
+                500,
+                WeightMode::Sqrt,
+                DEFAULT_DECIMALS,
+                false,
+            );
+            assert_defaults(
+                &Staking::new_with_decimals(700, 18),
+                700,
+                WeightMode::Linear,
+                18,
+                false,
+            );
+            assert_defaults(
+                &Staking::new_with_skip_zero_claims(800, true),
+                800,
+                WeightMode::Linear,
+                DEFAULT_DECIMALS,
+                true,
+            );
+
+            let staking_contract_instance =
+                Staking::new_with_positions(2000, vec![(accounts.bob, 10, 0)]);
+            assert_defaults(
+                &staking_contract_instance,
+                2000,
+                WeightMode::Linear,
+                DEFAULT_DECIMALS,
+                false,
+            );
+            assert_eq!(staking_contract_instance.get_total_staked(), 10);
+
+            // `new_cloned` reads the source configuration through a contract call, which the
+            // off-chain environment cannot make. It runs the same `init_defaults` before applying
+            // that configuration, see `applying_a_config_should_reproduce_the_source`
+            assert_reverts(
+                || {
+                    let _ = Staking::new_cloned(accounts.django);
+                },
+                "off-chain environment does not support contract invocation",
+            );
+        }
+
+        #[ink::test]
+        fn decimals_should_be_reported_with_the_raw_reward() {
+            fund_default_accounts();
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new_with_decimals(1000, 18);
+            assert_eq!(staking_contract_instance.get_decimals(), 18);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+
+            assert_eq!(
+                staking_contract_instance.rewards_for_user_scaled(alice),
+                (staking_contract_instance.rewards_for_user(alice), 18)
+            );
+            assert_eq!(
+                staking_contract_instance.rewards_for_user_scaled(alice).0,
+                10
+            );
+        }
+
+        #[ink::test]
+        fn deployment_with_positions_works() {
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+
+            let staking = Staking::new_with_positions(
+                1000,
+                vec![(accounts.alice, 10, 0), (accounts.bob, 25, 0)],
+            );
+            assert_eq!(staking.get_account_stake(accounts.alice), 10);
+            assert_eq!(staking.get_account_stake(accounts.bob), 25);
+            assert_eq!(staking.get_account_stake(accounts.charlie), 0);
+            assert_eq!(staking.get_total_staked(), 35);
+            assert_eq!(staking.staked_addresses, vec![accounts.alice, accounts.bob]);
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "Duplicate account in initial positions")]
+        fn deployment_with_duplicate_positions_should_not_work() {
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+
+            let _ = Staking::new_with_positions(
+                1000,
+                vec![(accounts.alice, 10, 0), (accounts.alice, 25, 0)],
+            );
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "Initial positions exceed the contract balance")]
+        fn deployment_with_unfunded_positions_should_not_work() {
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            set_contract_account();
+
+            let _ = Staking::new_with_positions(1000, vec![(accounts.alice, 10, 0)]);
+        }
+
+        #[ink::test]
+        fn batch_stake_for_should_not_exceed_the_contract_balance() {
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            ink_env::test::set_account_balance::<ink_env::DefaultEnvironment>(contract, 30);
+
+            assert_eq!(
+                staking_contract_instance
+                    .batch_stake_for(vec![(accounts.bob, 20), (accounts.charlie, 20)]),
+                Err(StakingError::Other(
+                    "positions exceed the contract balance".to_owned()
+                ))
+            );
+            assert_eq!(staking_contract_instance.get_total_staked(), 0);
+
+            assert_eq!(
+                staking_contract_instance
+                    .batch_stake_for(vec![(accounts.bob, 20), (accounts.charlie, 10)]),
+                Ok(())
+            );
+            assert_eq!(
+                staking_contract_instance.get_account_stake(accounts.bob),
+                20
+            );
+            assert_eq!(
+                staking_contract_instance.get_account_stake(accounts.charlie),
+                10
+            );
+            assert_eq!(staking_contract_instance.get_total_staked(), 30);
+
+            assert_eq!(
+                staking_contract_instance.batch_stake_for(vec![(accounts.django, 1)]),
+                Err(StakingError::Other(
+                    "positions exceed the contract balance".to_owned()
+                ))
+            );
+        }
+
+        #[ink::test]
+        fn batch_stake_for_should_be_bounded_by_max_batch() {
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            ink_env::test::set_account_balance::<ink_env::DefaultEnvironment>(contract, 1000);
+
+            let positions = |count: u32| -> Vec<(AccountId, Balance)> {
+                (1..=count)
+                    .map(|i| (AccountId::from([i as u8; 32]), 1))
+                    .collect()
+            };
+            assert_eq!(
+                staking_contract_instance.batch_stake_for(positions(MAX_BATCH + 1)),
+                Err(StakingError::Other("batch too large".to_owned()))
+            );
+            assert_eq!(staking_contract_instance.get_staker_count(), 0);
+
+            assert_eq!(
+                staking_contract_instance.batch_stake_for(positions(MAX_BATCH)),
+                Ok(())
+            );
+            assert_eq!(staking_contract_instance.get_staker_count(), MAX_BATCH);
+        }
+
+        #[ink::test]
+        fn batch_stake_for_should_import_all_or_nothing() {
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            ink_env::test::set_account_balance::<ink_env::DefaultEnvironment>(contract, 1000);
+            assert_eq!(staking_contract_instance.set_min_stake(10), Ok(()));
+            assert_eq!(staking_contract_instance.blacklist(accounts.django), Ok(()));
+
+            // a bad entry late in the batch rejects the entries before it too
+            assert_eq!(
+                staking_contract_instance.batch_stake_for(vec![
+                    (accounts.bob, 20),
+                    (accounts.charlie, 20),
+                    (accounts.django, 20)
+                ]),
+                Err(StakingError::Other("blacklisted".to_owned()))
+            );
+            assert_eq!(
+                staking_contract_instance
+                    .batch_stake_for(vec![(accounts.bob, 20), (accounts.charlie, 5)]),
+                Err(StakingError::Other(
+                    "stake below the minimum stake".to_owned()
+                ))
+            );
+            assert_eq!(staking_contract_instance.get_staker_count(), 0);
+            assert_eq!(staking_contract_instance.get_total_staked(), 0);
+
+            // every entry must reach the minimum, counting the earlier entries for the account
+            assert_eq!(
+                staking_contract_instance
+                    .batch_stake_for(vec![(accounts.charlie, 5), (accounts.charlie, 5)]),
+                Err(StakingError::Other(
+                    "stake below the minimum stake".to_owned()
+                ))
+            );
+            assert_eq!(
+                staking_contract_instance
+                    .batch_stake_for(vec![(accounts.charlie, 10), (accounts.charlie, 5)]),
+                Ok(())
+            );
+            assert_eq!(
+                staking_contract_instance.get_account_stake(accounts.charlie),
+                15
+            );
+        }
+
+        #[ink::test]
+        fn first_time_staking_should_work() {
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(staking_contract_instance.get_account_stake(alice), 0);
+
+            let stake = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
+            assert_eq!(stake, Ok(()));
+            assert_eq!(staking_contract_instance.get_account_stake(alice), 10);
+
+            // contract now has 10 coins more
+            let contract_balance = get_account_balance::<ink_env::DefaultEnvironment>(
+                staking_contract_instance.env().account_id(),
+            )
+            .unwrap();
+            assert_eq!(1000010, contract_balance);
+
+            assert!(staking_contract_instance.staked_addresses.contains(&alice));
+
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            assert_eq!(1, emitted_events.len());
+            assert_staked_event(&emitted_events[0], &alice, 10);
+        }
+
+        #[ink::test]
+        fn increasing_existing_stake_should_work() {
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(staking_contract_instance.get_account_stake(alice), 0);
+
+            let stake = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
+            assert_eq!(stake, Ok(()));
+            assert_eq!(staking_contract_instance.get_account_stake(alice), 10);
+
+            let stake_again = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
+            assert_eq!(stake_again, Ok(()));
+            assert_eq!(staking_contract_instance.get_account_stake(alice), 20);
+        }
+
+        #[ink::test]
+        fn stake_for_should_credit_the_beneficiary() {
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            set_contract_account();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let stake =
+                ink_env::pay_with_call!(staking_contract_instance.stake_for(accounts.bob), 10);
+            assert_eq!(stake, Ok(()));
+            assert_eq!(
+                staking_contract_instance.get_account_stake(accounts.alice),
+                0
+            );
+            assert_eq!(
+                staking_contract_instance.get_account_stake(accounts.bob),
+                10
+            );
+
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            assert_staked_event(&emitted_events[0], &accounts.bob, 10);
+        }
+
+        #[ink::test]
+        fn transfer_and_migrate_should_move_stake() {
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            set_contract_account();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 30);
+
+            assert_eq!(
+                staking_contract_instance.transfer_stake(accounts.bob, 10),
+                Ok(())
+            );
+            assert_eq!(
+                staking_contract_instance.get_account_stake(accounts.alice),
+                20
+            );
+            assert_eq!(
+                staking_contract_instance.get_account_stake(accounts.bob),
+                10
+            );
+            assert_eq!(staking_contract_instance.get_total_staked(), 30);
+
+            // neither side may end up below the minimum stake
+            assert_eq!(staking_contract_instance.set_min_stake(10), Ok(()));
+            assert_eq!(
+                staking_contract_instance.transfer_stake(accounts.bob, 15),
+                Err(StakingError::Other(
+                    "remaining stake would be below the minimum stake".to_owned()
+                ))
+            );
+            assert_eq!(
+                staking_contract_instance.transfer_stake(accounts.django, 5),
+                Err(StakingError::Other(
+                    "stake below the minimum stake".to_owned()
+                ))
+            );
+            assert_eq!(
+                staking_contract_instance.get_account_stake(accounts.alice),
+                20
+            );
+            // a top-up of an existing position only needs to reach the minimum in total
+            assert_eq!(
+                staking_contract_instance.transfer_stake(accounts.bob, 5),
+                Ok(())
+            );
+            assert_eq!(
+                staking_contract_instance.get_account_stake(accounts.bob),
+                15
+            );
+            assert_eq!(staking_contract_instance.get_total_staked(), 30);
+
+            assert_eq!(
+                staking_contract_instance.migrate_position(accounts.charlie),
+                Ok(())
+            );
+            assert_eq!(
+                staking_contract_instance.get_account_stake(accounts.alice),
+                0
+            );
+            assert_eq!(
+                staking_contract_instance.get_account_stake(accounts.charlie),
+                15
+            );
+            assert_eq!(
+                staking_contract_instance.staked_addresses,
+                vec![accounts.charlie, accounts.bob]
+            );
+        }
+
+        #[ink::test]
+        fn staking_with_referral_should_credit_the_referrer_once() {
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            set_contract_account();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(
+                staking_contract_instance.set_referral_bonus(5, 0, 5),
+                Err(StakingError::Other(
+                    "referral bonus requires a minimum stake and lock".to_owned()
+                ))
+            );
+            assert_eq!(
+                staking_contract_instance.set_referral_bonus(5, 10, 5),
+                Ok(())
+            );
+            assert_eq!(
+                staking_contract_instance.get_referral_requirements(),
+                (10, 5)
+            );
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+
+            let django_balance =
+                get_account_balance::<ink_env::DefaultEnvironment>(accounts.django).unwrap();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            let stake = ink_env::pay_with_call!(
+                staking_contract_instance.stake_with_referral(accounts.django),
+                10
+            );
+            assert_eq!(stake, Ok(()));
+            assert_eq!(
+                staking_contract_instance.get_account_stake(accounts.bob),
+                10
+            );
+            assert_eq!(
+                staking_contract_instance.get_referrer(accounts.bob),
+                Some(accounts.django)
+            );
+            assert_eq!(staking_contract_instance.referral_count(accounts.django), 1);
+            assert_eq!(
+                staking_contract_instance.get_pending_referral_bonus(accounts.bob),
+                Some((5, 5))
+            );
+
+            // the bonus vests over the referral lock
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.django);
+            assert_eq!(
+                staking_contract_instance.claim_referral_bonus(accounts.bob),
+                Err(StakingError::Other(
+                    "referral bonus has not vested yet".to_owned()
+                ))
+            );
+            for _ in 0..5 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(
+                staking_contract_instance.claim_referral_bonus(accounts.bob),
+                Ok(())
+            );
+            assert_eq!(
+                get_account_balance::<ink_env::DefaultEnvironment>(accounts.django).unwrap(),
+                django_balance + 5
+            );
+            assert_eq!(staking_contract_instance.get_reward_pool(), 95);
+            assert_eq!(
+                staking_contract_instance.claim_referral_bonus(accounts.bob),
+                Err(StakingError::Other("no referral bonus to claim".to_owned()))
+            );
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+
+            // a later stake neither changes the referrer nor pays another bonus
+            let stake = ink_env::pay_with_call!(
+                staking_contract_instance.stake_with_referral(accounts.eve),
+                10
+            );
+            assert_eq!(stake, Ok(()));
+            assert_eq!(
+                staking_contract_instance.get_referrer(accounts.bob),
+                Some(accounts.django)
+            );
+            assert_eq!(staking_contract_instance.referral_count(accounts.eve), 0);
+            assert_eq!(staking_contract_instance.get_reward_pool(), 95);
+
+            assert_reverts(
+                || {
+                    let _ = ink_env::pay_with_call!(
+                        staking_contract_instance.stake_with_referral(accounts.bob),
+                        10
+                    );
+                },
+                "cannot refer yourself",
+            );
+        }
+
+        #[ink::test]
+        fn referral_bonus_should_need_the_referred_stake_to_stay() {
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            set_contract_account();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(
+                staking_contract_instance.set_referral_bonus(5, 10, 5),
+                Ok(())
+            );
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+
+            // a stake below the referral minimum owes no bonus
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.charlie);
+            let _ = ink_env::pay_with_call!(
+                staking_contract_instance.stake_with_referral(accounts.django),
+                5
+            );
+            assert_eq!(
+                staking_contract_instance.get_pending_referral_bonus(accounts.charlie),
+                None
+            );
+
+            // a referred stake withdrawn before the claim does not pay either
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            let _ = ink_env::pay_with_call!(
+                staking_contract_instance.stake_with_referral(accounts.django),
+                10
+            );
+            for _ in 0..5 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.unstake(10), Ok(()));
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.django);
+            assert_eq!(
+                staking_contract_instance.claim_referral_bonus(accounts.bob),
+                Err(StakingError::Other(
+                    "referred stake is below the referral minimum".to_owned()
+                ))
+            );
+
+            // only the referrer can claim
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.eve);
+            assert_eq!(
+                staking_contract_instance.claim_referral_bonus(accounts.bob),
+                Err(StakingError::Other("no referral bonus to claim".to_owned()))
+            );
+        }
+
+        #[ink::test]
+        fn referral_bonus_should_wait_for_the_pool_to_cover_it() {
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            set_contract_account();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(
+                staking_contract_instance.set_referral_bonus(5, 10, 5),
+                Ok(())
+            );
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 3);
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            let _ = ink_env::pay_with_call!(
+                staking_contract_instance.stake_with_referral(accounts.django),
+                10
+            );
+            for _ in 0..5 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+
+            let django_balance =
+                get_account_balance::<ink_env::DefaultEnvironment>(accounts.django).unwrap();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.django);
+            assert_eq!(
+                staking_contract_instance.claim_referral_bonus(accounts.bob),
+                Err(StakingError::Other(
+                    "reward pool cannot cover the referral bonus".to_owned()
+                ))
+            );
+            assert_eq!(
+                staking_contract_instance.get_pending_referral_bonus(accounts.bob),
+                Some((5, 5))
+            );
+            assert_eq!(staking_contract_instance.get_reward_pool(), 3);
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 2);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.django);
+            assert_eq!(
+                staking_contract_instance.claim_referral_bonus(accounts.bob),
+                Ok(())
+            );
+            assert_eq!(
+                get_account_balance::<ink_env::DefaultEnvironment>(accounts.django).unwrap(),
+                django_balance + 5
+            );
+            assert_eq!(staking_contract_instance.get_reward_pool(), 0);
+        }
+
+        #[ink::test]
+        fn staking_should_require_acknowledgement() {
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(
+                staking_contract_instance.set_acknowledgement_required(true),
+                Ok(())
+            );
+            assert!(staking_contract_instance.is_acknowledgement_required());
+
+            assert_reverts(
+                || {
+                    let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
+                },
+                "must acknowledge first",
+            );
+            assert_eq!(staking_contract_instance.get_account_stake(alice), 0);
+
+            assert_eq!(staking_contract_instance.acknowledge(), Ok(()));
+            assert!(staking_contract_instance.has_acknowledged(alice));
+            let stake = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
+            assert_eq!(stake, Ok(()));
+            assert_eq!(staking_contract_instance.get_account_stake(alice), 10);
+
+            // staking on behalf of someone needs their acknowledgement too
+            let bob = default_accounts::<ink_env::DefaultEnvironment>().bob;
+            assert_reverts(
+                || {
+                    let _ = ink_env::pay_with_call!(staking_contract_instance.stake_for(bob), 10);
+                },
+                "must acknowledge first",
+            );
+            assert_eq!(staking_contract_instance.get_account_stake(bob), 0);
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(bob);
+            assert_eq!(staking_contract_instance.acknowledge(), Ok(()));
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+            let stake = ink_env::pay_with_call!(staking_contract_instance.stake_for(bob), 10);
+            assert_eq!(stake, Ok(()));
+            assert_eq!(staking_contract_instance.get_account_stake(bob), 10);
+        }
+
+        #[ink::test]
+        fn staking_for_the_zero_account_should_not_work() {
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let zero_account = AccountId::from([0u8; 32]);
+
+            assert_reverts(
+                || {
+                    let _ = ink_env::pay_with_call!(
+                        staking_contract_instance.stake_for(zero_account),
+                        10
+                    );
+                },
+                "invalid account",
+            );
+            assert_eq!(staking_contract_instance.get_account_stake(zero_account), 0);
+            assert_eq!(staking_contract_instance.get_total_staked(), 0);
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(zero_account);
+            assert_reverts(
+                || {
+                    let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 0);
+                },
+                "invalid account",
+            );
+        }
+
+        #[ink::test]
+        fn contract_should_not_be_a_stake_target() {
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 30);
+
+            let expected_error = Err(StakingError::Other(
+                "the contract itself cannot hold a stake".to_owned(),
+            ));
+            assert_reverts(
+                || {
+                    let _ =
+                        ink_env::pay_with_call!(staking_contract_instance.stake_for(contract), 10);
+                },
+                "the contract itself cannot hold a stake",
+            );
+            assert_eq!(
+                staking_contract_instance.transfer_stake(contract, 10),
+                expected_error
+            );
+            assert_eq!(
+                staking_contract_instance.migrate_position(contract),
+                expected_error
+            );
+            assert_eq!(staking_contract_instance.get_account_stake(contract), 0);
+            assert_eq!(
+                staking_contract_instance.get_account_stake(accounts.alice),
+                30
+            );
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "Must stake more than 0")]
+        fn staking_zero_should_not_be_allowed() {
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 0);
+        }
+
+        #[ink::test]
+        fn staking_while_paused_should_not_work() {
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(staking_contract_instance.pause(), Ok(()));
+            assert!(staking_contract_instance.is_paused());
+
+            assert_reverts(
+                || {
+                    let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
+                },
+                "contract is paused",
+            );
+
+            assert_eq!(staking_contract_instance.unpause(), Ok(()));
+            let stake = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
+            assert_eq!(stake, Ok(()));
+        }
+
+        #[ink::test]
+        fn shutdown_should_not_pay_out_more_than_the_pool() {
+            fund_default_accounts();
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(
+                staking_contract_instance.set_reward_vesting_blocks(100),
+                Ok(())
+            );
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 15);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.claim_reward(), Ok(()));
+            assert_eq!(staking_contract_instance.get_total_vesting(), 10);
+            assert_eq!(staking_contract_instance.get_reward_pool(), 5);
+
+            // bob accrued 10 more, but only 5 are left in the pool
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.rewards_for_user(accounts.bob), 10);
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+            assert_eq!(staking_contract_instance.pause(), Ok(()));
+            let bob_balance =
+                get_account_balance::<ink_env::DefaultEnvironment>(accounts.bob).unwrap();
+            assert_eq!(staking_contract_instance.shutdown(), Ok(()));
+            assert_eq!(
+                get_account_balance::<ink_env::DefaultEnvironment>(accounts.bob).unwrap(),
+                bob_balance + 10 * STAKE_SCALE + 5
+            );
+            assert_eq!(staking_contract_instance.get_reward_pool(), 0);
+
+            // the vesting schedule is still backed and can be released in full
+            assert_eq!(
+                get_account_balance::<ink_env::DefaultEnvironment>(contract).unwrap(),
+                10
+            );
+            for _ in 0..100 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(staking_contract_instance.claim_vested(), Ok(()));
+            assert_eq!(
+                get_account_balance::<ink_env::DefaultEnvironment>(accounts.bob).unwrap(),
+                bob_balance + 10 * STAKE_SCALE + 5 + 10
+            );
+            assert_eq!(staking_contract_instance.get_total_vesting(), 0);
+        }
+
+        #[ink::test]
+        fn shutdown_should_return_all_funds() {
+            fund_default_accounts();
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 20 * STAKE_SCALE);
+
+            for _ in 0..5 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+            assert_eq!(
+                staking_contract_instance.shutdown(),
+                Err(StakingError::Other(
+                    "contract must be paused before shutdown".to_owned()
+                ))
+            );
+            assert_eq!(staking_contract_instance.pause(), Ok(()));
+
+            let alice_balance =
+                get_account_balance::<ink_env::DefaultEnvironment>(accounts.alice).unwrap();
+            let bob_balance =
+                get_account_balance::<ink_env::DefaultEnvironment>(accounts.bob).unwrap();
+            assert_eq!(staking_contract_instance.shutdown(), Ok(()));
+
+            // alice is also the owner and receives the 85 left in the reward pool
+            assert_eq!(
+                get_account_balance::<ink_env::DefaultEnvironment>(accounts.alice).unwrap(),
+                alice_balance + 10 * STAKE_SCALE + 5 + 85
+            );
+            assert_eq!(
+                get_account_balance::<ink_env::DefaultEnvironment>(accounts.bob).unwrap(),
+                bob_balance + 20 * STAKE_SCALE + 10
+            );
+            assert_eq!(
+                get_account_balance::<ink_env::DefaultEnvironment>(contract).unwrap(),
+                0
+            );
+
+            assert_eq!(
+                staking_contract_instance.get_account_stake(accounts.alice),
+                0
+            );
+            assert_eq!(staking_contract_instance.get_account_stake(accounts.bob), 0);
+            assert_eq!(staking_contract_instance.staked_addresses, Vec::default());
+            assert_eq!(staking_contract_instance.get_total_staked(), 0);
+            assert_eq!(staking_contract_instance.get_reward_pool(), 0);
+        }
+
+        #[ink::test]
+        fn claiming_should_work() {
+            fund_default_accounts();
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+            let initial_balance =
+                get_account_balance::<ink_env::DefaultEnvironment>(alice).unwrap();
+
+            let mut staking_contract_instance = Staking::new(1000);
+
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+
+            assert_eq!(
+                staking_contract_instance.get_account_stake(alice),
+                10 * STAKE_SCALE
+            );
+
+            for _ in 0..5 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+
+            let to_be_claimed = staking_contract_instance.rewards_for_user(alice);
+            assert_eq!(5, to_be_claimed);
+
+            let claim = staking_contract_instance.claim_reward();
+            assert_eq!(claim, Ok(()));
+
+            let alice_balance = get_account_balance::<ink_env::DefaultEnvironment>(alice).unwrap();
+            assert_eq!(alice_balance, initial_balance + 10 * STAKE_SCALE + 100 + 5);
+
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            assert_eq!(3, emitted_events.len());
+            assert_staked_event(&emitted_events[0], &alice, 10 * STAKE_SCALE);
+            assert_claimed_event(&emitted_events[2], &alice, 5);
+        }
+
+        #[ink::test]
+        fn unstake_to_should_send_the_principal_to_the_recipient() {
+            fund_default_accounts();
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+
+            for _ in 0..5 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+
+            // the principal cannot be burnt or parked in the contract
+            assert_eq!(
+                staking_contract_instance.unstake_to(10 * STAKE_SCALE, AccountId::from([0u8; 32])),
+                Err(StakingError::Other("invalid account".to_owned()))
+            );
+            assert_eq!(
+                staking_contract_instance.unstake_to(10 * STAKE_SCALE, contract),
+                Err(StakingError::Other(
+                    "the contract itself cannot hold a stake".to_owned()
+                ))
+            );
+
+            let alice_balance =
+                get_account_balance::<ink_env::DefaultEnvironment>(accounts.alice).unwrap();
+            let django_balance =
+                get_account_balance::<ink_env::DefaultEnvironment>(accounts.django).unwrap();
+            assert_eq!(
+                staking_contract_instance.unstake_to(10 * STAKE_SCALE, accounts.django),
+                Ok(())
+            );
+
+            assert_eq!(
+                get_account_balance::<ink_env::DefaultEnvironment>(accounts.django).unwrap(),
+                django_balance + 10 * STAKE_SCALE
+            );
+            assert_eq!(
+                get_account_balance::<ink_env::DefaultEnvironment>(accounts.alice).unwrap(),
+                alice_balance + 5
+            );
+            assert_eq!(
+                staking_contract_instance.get_account_stake(accounts.alice),
+                0
+            );
+
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            assert_claimed_event(&emitted_events[2], &accounts.alice, 5);
+            assert_unstaked_event(&emitted_events[3], &accounts.alice, 10 * STAKE_SCALE);
+        }
+
+        #[ink::test]
+        fn unstake_should_respect_the_max_per_tx() {
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(
+                staking_contract_instance.set_max_unstake_per_tx(Some(10)),
+                Ok(())
+            );
+            assert_eq!(staking_contract_instance.get_max_unstake_per_tx(), Some(10));
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 20);
+
+            assert_eq!(
+                staking_contract_instance.unstake(11),
+                Err(StakingError::Other("exceeds max unstake per tx".to_owned()))
+            );
+            assert_eq!(staking_contract_instance.get_account_stake(alice), 20);
+
+            assert_eq!(staking_contract_instance.unstake(10), Ok(()));
+            assert_eq!(staking_contract_instance.unstake(10), Ok(()));
+            assert_eq!(staking_contract_instance.get_account_stake(alice), 0);
+        }
+
+        #[ink::test]
+        fn the_max_per_tx_should_not_trap_a_stake_above_the_minimum() {
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 1000);
+            assert_eq!(staking_contract_instance.set_min_stake(100), Ok(()));
+            assert_eq!(
+                staking_contract_instance.set_max_unstake_per_tx(Some(10)),
+                Ok(())
+            );
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 105);
+
+            // a smaller step still has to respect the minimum
+            assert_eq!(staking_contract_instance.unstake(5), Ok(()));
+            assert_eq!(
+                staking_contract_instance.unstake(5),
+                Err(StakingError::UnstakeError(
+                    "remaining stake would be below the minimum stake".to_owned()
+                ))
+            );
+
+            // withdrawing the whole cap every time gets the position out
+            for _ in 0..10 {
+                assert_eq!(staking_contract_instance.unstake(10), Ok(()));
+            }
+            assert_eq!(staking_contract_instance.get_account_stake(alice), 0);
+        }
+
+        #[ink::test]
+        fn failed_full_unstake_should_keep_the_staker_listed() {
+            fund_default_accounts();
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            let contract = set_contract_account();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 5);
+
+            for _ in 0..5 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+
+            // the principal is there but the reward cannot be paid
+            ink_env::test::set_account_balance::<ink_env::DefaultEnvironment>(
+                contract,
+                10 * STAKE_SCALE,
+            );
+            assert_eq!(
+                staking_contract_instance.unstake(10 * STAKE_SCALE),
+                Err(StakingError::UnstakeError(
+                    "contract balance cannot cover the unstake".to_owned()
+                ))
+            );
+            assert_eq!(staking_contract_instance.staked_addresses, vec![alice]);
+            assert_eq!(
+                staking_contract_instance.get_account_stake(alice),
+                10 * STAKE_SCALE
+            );
+            assert_eq!(
+                staking_contract_instance.get_total_staked(),
+                10 * STAKE_SCALE
+            );
+            assert_eq!(staking_contract_instance.rewards_for_user(alice), 5);
+
+            ink_env::test::set_account_balance::<ink_env::DefaultEnvironment>(
+                contract,
+                10 * STAKE_SCALE + 5,
+            );
+            assert_eq!(staking_contract_instance.unstake(10 * STAKE_SCALE), Ok(()));
+            assert!(staking_contract_instance.staked_addresses.is_empty());
+            assert_eq!(staking_contract_instance.get_total_staked(), 0);
+        }
+
+        #[ink::test]
+        fn failed_reward_transfer_should_keep_reward_claimable() {
+            fund_default_accounts();
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let contract = staking_contract_instance.env().account_id();
+
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+
+            for _ in 0..5 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+
+            // drain the contract so the reward cannot be paid
+            ink_env::test::set_account_balance::<ink_env::DefaultEnvironment>(contract, 0);
+            let claim = staking_contract_instance.claim_reward();
+            assert_eq!(
+                claim,
+                Err(StakingError::ClaimingRewardError(
+                    "reward pool cannot cover the claim".to_owned()
+                ))
+            );
+            assert_eq!(staking_contract_instance.rewards_for_user(alice), 5);
+
+            ink_env::test::set_account_balance::<ink_env::DefaultEnvironment>(
+                contract,
+                10 * STAKE_SCALE + 100,
+            );
+            let alice_balance = get_account_balance::<ink_env::DefaultEnvironment>(alice).unwrap();
+
+            let claim = staking_contract_instance.claim_reward();
+            assert_eq!(claim, Ok(()));
+            assert_eq!(staking_contract_instance.rewards_for_user(alice), 0);
+            assert_eq!(
+                get_account_balance::<ink_env::DefaultEnvironment>(alice).unwrap(),
+                alice_balance + 5
+            );
+        }
+
+        #[ink::test]
+        fn get_my_info_should_return_the_callers_position() {
+            fund_default_accounts();
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(staking_contract_instance.set_lock_period(10), Ok(()));
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 20 * STAKE_SCALE);
+
+            for _ in 0..2 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+
+            assert_eq!(
+                staking_contract_instance.get_my_info(),
+                Some(StakeInfo {
+                    stake_amount: 20 * STAKE_SCALE,
+                    rewards: 4,
+                    unlock_block: 11,
+                    last_action_block: 1,
+                })
+            );
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+            assert_eq!(
+                staking_contract_instance.get_my_info(),
+                Some(StakeInfo {
+                    stake_amount: 10 * STAKE_SCALE,
+                    rewards: 3,
+                    unlock_block: 10,
+                    last_action_block: 0,
+                })
+            );
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.charlie);
+            assert_eq!(staking_contract_instance.get_my_info(), None);
+        }
+
+        #[ink::test]
+        fn reward_invariant_should_detect_inflated_rewards() {
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 20);
+
+            for _ in 0..3 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.claim_reward(), Ok(()));
+            assert!(staking_contract_instance.check_reward_invariant());
+
+            // simulate accounting drift on alice's position
+            let staking_position = staking_contract_instance
+                .stake_positions
+                .get(accounts.alice)
+                .unwrap();
+            staking_contract_instance.stake_positions.insert(
+                accounts.alice,
+                &StakingPosition {
+                    pending_rewards: 1_000,
+                    ..staking_position
+                },
+            );
+            assert!(!staking_contract_instance.check_reward_invariant());
+        }
+
+        #[ink::test]
+        fn contract_stats_should_match_getters() {
+            fund_default_accounts();
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 30 * STAKE_SCALE);
+
+            for _ in 0..5 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.claim_reward(), Ok(()));
+
+            let stats = staking_contract_instance.get_contract_stats();
+            assert_eq!(
+                stats,
+                ContractStats {
+                    total_staked: 50 * STAKE_SCALE,
+                    staker_count: 2,
+                    reward_pool: 85,
+                    total_rewards_paid: 15,
+                    apy: 1000,
+                }
+            );
+            assert_eq!(
+                stats.total_staked,
+                staking_contract_instance.get_total_staked()
+            );
+            assert_eq!(
+                stats.staker_count,
+                staking_contract_instance.get_staker_count()
+            );
+            assert_eq!(
+                stats.reward_pool,
+                staking_contract_instance.get_reward_pool()
+            );
+            assert_eq!(
+                stats.total_rewards_paid,
+                staking_contract_instance.get_total_rewards_paid()
+            );
+            assert_eq!(stats.apy, staking_contract_instance.get_apy());
+        }
+
+        #[ink::test]
+        fn claim_to_should_pay_the_recipient() {
+            fund_default_accounts();
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            set_contract_account();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+
+            for _ in 0..5 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+
+            let alice_balance =
+                get_account_balance::<ink_env::DefaultEnvironment>(accounts.alice).unwrap();
+            let charlie_balance =
+                get_account_balance::<ink_env::DefaultEnvironment>(accounts.charlie).unwrap();
+
+            let claim = staking_contract_instance.claim_to(accounts.charlie);
+            assert_eq!(claim, Ok(()));
+            assert_eq!(
+                staking_contract_instance.rewards_for_user(accounts.alice),
+                0
+            );
+            assert_eq!(
+                get_account_balance::<ink_env::DefaultEnvironment>(accounts.alice).unwrap(),
+                alice_balance
+            );
+            assert_eq!(
+                get_account_balance::<ink_env::DefaultEnvironment>(accounts.charlie).unwrap(),
+                charlie_balance + 5
+            );
+
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            assert_eq!(3, emitted_events.len());
+            assert_claimed_event(&emitted_events[2], &accounts.alice, 5);
+        }
+
+        #[ink::test]
+        fn frequent_claims_should_not_lose_sub_unit_rewards() {
+            fund_default_accounts();
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            set_contract_account();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+
+            // both stakes earn a tenth of a unit per block
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), STAKE_SCALE);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), STAKE_SCALE);
+
+            let alice_balance =
+                get_account_balance::<ink_env::DefaultEnvironment>(accounts.alice).unwrap();
+            let bob_balance =
+                get_account_balance::<ink_env::DefaultEnvironment>(accounts.bob).unwrap();
+
+            // alice claims every block, bob only once at the end
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+            for _ in 0..25 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+                assert_eq!(staking_contract_instance.claim_reward(), Ok(()));
+            }
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(staking_contract_instance.claim_reward(), Ok(()));
+
+            assert_eq!(
+                get_account_balance::<ink_env::DefaultEnvironment>(accounts.alice).unwrap(),
+                alice_balance + 2
+            );
+            assert_eq!(
+                get_account_balance::<ink_env::DefaultEnvironment>(accounts.bob).unwrap(),
+                bob_balance + 2
+            );
+
+            // the half unit left over keeps accruing towards the next one
+            for _ in 0..5 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(
+                staking_contract_instance.rewards_for_user(accounts.alice),
+                1
+            );
+            assert_eq!(staking_contract_instance.rewards_for_user(accounts.bob), 1);
+        }
+
+        #[ink::test]
+        fn compound_should_restake_the_reward() {
+            fund_default_accounts();
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+
+            for _ in 0..5 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+
+            assert_eq!(staking_contract_instance.compound(), Ok(()));
+            assert_eq!(
+                staking_contract_instance.get_account_stake(alice),
+                10 * STAKE_SCALE + 5
+            );
+            assert_eq!(
+                staking_contract_instance.get_total_staked(),
+                10 * STAKE_SCALE + 5
+            );
+            assert_eq!(staking_contract_instance.get_reward_pool(), 95);
+            assert_eq!(staking_contract_instance.rewards_for_user(alice), 0);
+        }
+
+        #[ink::test]
+        fn compound_should_not_restake_more_than_the_pool() {
+            fund_default_accounts();
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            set_contract_account();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(staking_contract_instance.set_keeper_fee_bps(1000), Ok(()));
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            for _ in 0..5 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+
+            // nothing backs the reward, so neither the stake nor the keeper may take it
+            let underfunded = Err(StakingError::ClaimingRewardError(
+                "reward pool cannot cover the claim".to_owned(),
+            ));
+            assert_eq!(staking_contract_instance.compound(), underfunded);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(
+                staking_contract_instance.compound_for_with_fee(accounts.alice),
+                underfunded
+            );
+            assert_eq!(
+                staking_contract_instance.get_account_stake(accounts.alice),
+                10 * STAKE_SCALE
+            );
+            assert_eq!(
+                staking_contract_instance.get_total_staked(),
+                10 * STAKE_SCALE
+            );
+            assert_eq!(
+                staking_contract_instance.rewards_for_user(accounts.alice),
+                5
+            );
+
+            // the whole principal can still leave
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+            assert_eq!(staking_contract_instance.unstake(10 * STAKE_SCALE), Ok(()));
+        }
+
+        #[ink::test]
+        fn claim_up_to_then_compound_should_split_the_reward() {
+            fund_default_accounts();
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            set_contract_account();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.rewards_for_user(alice), 10);
+
+            let alice_balance = get_account_balance::<ink_env::DefaultEnvironment>(alice).unwrap();
+            assert_eq!(
+                staking_contract_instance.claim_up_to_then_compound(4),
+                Ok(())
+            );
+
+            assert_eq!(
+                get_account_balance::<ink_env::DefaultEnvironment>(alice).unwrap(),
+                alice_balance + 4
+            );
+            assert_eq!(
+                staking_contract_instance.get_account_stake(alice),
+                10 * STAKE_SCALE + 6
+            );
+            assert_eq!(
+                staking_contract_instance.get_total_staked(),
+                10 * STAKE_SCALE + 6
+            );
+            assert_eq!(staking_contract_instance.get_reward_pool(), 90);
+            assert_eq!(staking_contract_instance.rewards_for_user(alice), 0);
+
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            assert_claimed_event(&emitted_events[2], &alice, 4);
+        }
+
+        #[ink::test]
+        fn keeper_should_earn_a_fee_for_compounding() {
+            fund_default_accounts();
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            set_contract_account();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(staking_contract_instance.set_keeper_fee_bps(1000), Ok(()));
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            let bob_balance =
+                get_account_balance::<ink_env::DefaultEnvironment>(accounts.bob).unwrap();
+            assert_eq!(
+                staking_contract_instance.compound_for_with_fee(accounts.alice),
+                Ok(())
+            );
+
+            // 10% of the reward of 10 goes to the keeper, the rest is restaked
+            assert_eq!(
+                get_account_balance::<ink_env::DefaultEnvironment>(accounts.bob).unwrap(),
+                bob_balance + 1
+            );
+            assert_eq!(
+                staking_contract_instance.get_account_stake(accounts.alice),
+                10 * STAKE_SCALE + 9
+            );
+            assert_eq!(staking_contract_instance.get_reward_pool(), 90);
+        }
+
+        #[ink::test]
+        fn rewards_should_not_be_paid_out_of_principal() {
+            fund_default_accounts();
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            let alice = accounts.alice;
+            set_contract_account();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+
+            for _ in 0..5 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+
+            // the contract holds the principal but the pool is empty
+            assert_eq!(staking_contract_instance.rewards_for_user(alice), 5);
+            assert_eq!(staking_contract_instance.claimable_now(alice), 0);
+            assert_eq!(
+                staking_contract_instance.claim_reward(),
+                Err(StakingError::ClaimingRewardError(
+                    "reward pool cannot cover the claim".to_owned()
+                ))
+            );
+
+            // the principal still comes back and the reward stays pending
+            assert_eq!(staking_contract_instance.unstake(10 * STAKE_SCALE), Ok(()));
+            assert_eq!(staking_contract_instance.rewards_for_user(alice), 5);
+
+            // claimable_now mirrors the burn of the real claim
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+            assert_eq!(
+                staking_contract_instance.set_reward_burn(2_000, accounts.django),
+                Ok(())
+            );
+            assert_eq!(staking_contract_instance.claimable_now(alice), 4);
+        }
+
+        #[ink::test]
+        fn claimable_now_should_be_zero_during_cooldown() {
+            fund_default_accounts();
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(staking_contract_instance.set_claim_cooldown(10), Ok(()));
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+
+            for _ in 0..5 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.claimable_now(alice), 5);
+            assert_eq!(staking_contract_instance.claim_reward(), Ok(()));
+
+            for _ in 0..3 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.rewards_for_user(alice), 3);
+            assert_eq!(staking_contract_instance.claimable_now(alice), 0);
+            assert_eq!(
+                staking_contract_instance.claim_reward(),
+                Err(StakingError::ClaimingRewardError(
+                    "claim is still on cooldown".to_owned()
+                ))
+            );
+
+            for _ in 0..7 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.claimable_now(alice), 10);
+        }
+
+        #[ink::test]
+        fn claimable_now_should_be_zero_when_the_contract_cannot_pay() {
+            fund_default_accounts();
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            let contract = set_contract_account();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+
+            for _ in 0..5 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            ink_env::test::set_account_balance::<ink_env::DefaultEnvironment>(contract, 4);
+            assert_eq!(staking_contract_instance.rewards_for_user(alice), 5);
+            assert_eq!(staking_contract_instance.claimable_now(alice), 0);
+        }
+
+        #[ink::test]
+        fn funding_should_record_the_block() {
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            for _ in 0..3 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            let funding_block = ink_env::block_number::<ink_env::DefaultEnvironment>();
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+
+            for _ in 0..2 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(
+                staking_contract_instance.get_last_funded_block(),
+                funding_block
+            );
+
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            let decoded_event = <Event as scale::Decode>::decode(&mut &emitted_events[0].data[..])
+                .expect("encountered invalid contract event data buffer");
+            if let Event::RewardPoolFunded(RewardPoolFunded {
+                funder,
+                amount,
+                block,
+            }) = decoded_event
+            {
+                assert_eq!(funder, alice);
+                assert_eq!(amount, 100);
+                assert_eq!(block, funding_block);
+            } else {
+                panic!("encountered unexpected event kind: expected a RewardPoolFunded event")
+            }
+        }
+
+        #[ink::test]
+        fn claiming_should_burn_a_share_of_the_reward() {
+            fund_default_accounts();
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            set_contract_account();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(
+                staking_contract_instance.set_reward_burn(2000, accounts.django),
+                Ok(())
+            );
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+
+            let alice_balance =
+                get_account_balance::<ink_env::DefaultEnvironment>(accounts.alice).unwrap();
+            let django_balance =
+                get_account_balance::<ink_env::DefaultEnvironment>(accounts.django).unwrap();
+            assert_eq!(staking_contract_instance.claim_reward(), Ok(()));
+
+            // 20% of the reward of 10 is burned
+            assert_eq!(
+                get_account_balance::<ink_env::DefaultEnvironment>(accounts.alice).unwrap(),
+                alice_balance + 8
+            );
+            assert_eq!(
+                get_account_balance::<ink_env::DefaultEnvironment>(accounts.django).unwrap(),
+                django_balance + 2
+            );
+            assert_eq!(staking_contract_instance.get_reward_pool(), 90);
+
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            assert_claimed_event(&emitted_events[2], &accounts.alice, 8);
+        }
+
+        #[ink::test]
+        fn claiming_while_not_staked_should_not_work() {
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+
+            let claim = staking_contract_instance.claim_reward();
+            assert_eq!(
+                claim,
+                Err(StakingError::ClaimingRewardError(
+                    "user doesnt seem to have a stake".to_owned()
+                ))
+            )
+        }
+
+        #[ink::test]
+        fn unstake_should_work() {
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(staking_contract_instance.get_account_stake(alice), 0);
+
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
+            assert_eq!(staking_contract_instance.get_account_stake(alice), 10);
+
+            let unstake_result = staking_contract_instance.unstake(10);
+            assert_eq!(unstake_result, Ok(()));
+            assert_eq!(staking_contract_instance.get_account_stake(alice), 0);
+            assert!(!staking_contract_instance.staked_addresses.contains(&alice));
+
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            assert_eq!(2, emitted_events.len());
+            assert_staked_event(&emitted_events[0], &alice, 10);
+            assert_unstaked_event(&emitted_events[1], &alice, 10);
+        }
+
+        #[ink::test]
+        fn partial_unstake_should_work() {
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(staking_contract_instance.get_account_stake(alice), 0);
+
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
+            assert_eq!(staking_contract_instance.get_account_stake(alice), 10);
+
+            let unstake_result = staking_contract_instance.unstake(5);
+            assert_eq!(unstake_result, Ok(()));
+            assert_eq!(staking_contract_instance.get_account_stake(alice), 5);
+            assert!(staking_contract_instance.staked_addresses.contains(&alice));
+
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            assert_eq!(2, emitted_events.len());
+            assert_staked_event(&emitted_events[0], &alice, 10);
+            assert_unstaked_event(&emitted_events[1], &alice, 5);
+        }
+
+        #[ink::test]
+        #[should_panic(expected = "Must unstake more than 0")]
+        fn unstake_zero_not_allowed() {
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(staking_contract_instance.get_account_stake(alice), 0);
+
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
+            assert_eq!(staking_contract_instance.get_account_stake(alice), 10);
+
+            let _ = staking_contract_instance.unstake(0);
+        }
+
+        #[ink::test]
+        fn unstake_percent_should_work() {
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 20);
+
+            assert_eq!(
+                staking_contract_instance.unstake_percent(10_001),
+                Err(StakingError::UnstakeError(
+                    "cannot unstake more than 10000 basis points".to_owned()
+                ))
+            );
+
+            assert_eq!(staking_contract_instance.unstake_percent(5000), Ok(()));
+            assert_eq!(staking_contract_instance.get_account_stake(alice), 10);
+
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            assert_unstaked_event(&emitted_events[1], &alice, 10);
+
+            assert_eq!(staking_contract_instance.unstake_percent(10_000), Ok(()));
+            assert_eq!(staking_contract_instance.get_account_stake(alice), 0);
+        }
+
+        #[ink::test]
+        fn unstake_more_than_staked_should_not_work() {
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(staking_contract_instance.get_account_stake(alice), 0);
+
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
+            assert_eq!(staking_contract_instance.get_account_stake(alice), 10);
+
+            let unstake = staking_contract_instance.unstake(11);
+            assert_eq!(
+                unstake,
+                Err(StakingError::UnstakeError(
+                    "unstake amount cannot be greater than staked amount".to_owned()
+                ))
+            )
+        }
+
+        #[ink::test]
+        fn unstake_should_work_iff_user_has_staked() {
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let unstake = staking_contract_instance.unstake(1);
+            assert_eq!(
+                unstake,
+                Err(StakingError::UnstakeError(
+                    "can only unstake if user has already staked".to_owned()
+                ))
+            )
+        }
+
+        #[ink::test]
+        fn staker_index_should_shift_after_removal() {
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            let mut staking_contract_instance = Staking::new(1000);
+
+            for account in [accounts.alice, accounts.bob, accounts.charlie] {
+                ink_env::test::set_caller::<ink_env::DefaultEnvironment>(account);
+                let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
+            }
+            assert_eq!(
+                staking_contract_instance.staker_index(accounts.alice),
+                Some(0)
+            );
+            assert_eq!(
+                staking_contract_instance.staker_index(accounts.bob),
+                Some(1)
+            );
+            assert_eq!(
+                staking_contract_instance.staker_index(accounts.charlie),
+                Some(2)
+            );
+            assert_eq!(
+                staking_contract_instance.staker_index(accounts.django),
+                None
+            );
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+            assert_eq!(staking_contract_instance.unstake(10), Ok(()));
+
+            assert_eq!(staking_contract_instance.staker_index(accounts.alice), None);
+            assert_eq!(
+                staking_contract_instance.staker_index(accounts.bob),
+                Some(0)
+            );
+            assert_eq!(
+                staking_contract_instance.staker_index(accounts.charlie),
+                Some(1)
+            );
+        }
+
+        #[ink::test]
+        fn dump_positions_should_work() {
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            let mut staking_contract_instance = Staking::new(1000);
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 20);
+
+            let positions = staking_contract_instance.dump_positions(0, 10).unwrap();
+            assert_eq!(positions.len(), 2);
+            assert_eq!(positions[0].0, accounts.alice);
+            assert_eq!(positions[0].1.stake_amount, 10);
+            assert_eq!(positions[0].1.last_action_block, 0);
+            assert_eq!(positions[1].0, accounts.bob);
+            assert_eq!(positions[1].1.stake_amount, 20);
+            assert_eq!(positions[1].1.last_action_block, 1);
+
+            let page = staking_contract_instance.dump_positions(1, 1).unwrap();
+            assert_eq!(page.len(), 1);
+            assert_eq!(page[0].0, accounts.bob);
+            assert_eq!(
+                staking_contract_instance.dump_positions(2, 10),
+                Ok(Vec::new())
+            );
+        }
+
+        #[ink::test]
+        fn dump_positions_should_be_bounded_by_max_batch() {
+            let staking_contract_instance = Staking::new(1000);
+
+            assert_eq!(
+                staking_contract_instance.dump_positions(0, MAX_BATCH),
+                Ok(Vec::new())
+            );
+            assert_eq!(
+                staking_contract_instance.dump_positions(0, MAX_BATCH + 1),
+                Err(StakingError::Other("batch too large".to_owned()))
+            );
+        }
+
+        #[ink::test]
+        fn activity_counts_should_track_operations() {
+            fund_default_accounts();
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(staking_contract_instance.get_activity_counts(), (0, 0, 0));
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+
+            for _ in 0..5 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.claim_reward(), Ok(()));
+
+            // unstaking pays out the accrued reward as well
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(staking_contract_instance.unstake(10 * STAKE_SCALE), Ok(()));
+
+            assert_eq!(staking_contract_instance.get_activity_counts(), (3, 1, 2));
+        }
+
+        #[ink::test]
+        fn remaining_lock_should_count_down() {
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(staking_contract_instance.set_lock_period(10), Ok(()));
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
+
+            assert!(staking_contract_instance.is_locked(alice));
+            assert_eq!(staking_contract_instance.remaining_lock(alice), 10);
+
+            for _ in 0..4 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert!(staking_contract_instance.is_locked(alice));
+            assert_eq!(staking_contract_instance.remaining_lock(alice), 6);
+
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert!(!staking_contract_instance.is_locked(alice));
+            assert_eq!(staking_contract_instance.remaining_lock(alice), 0);
+        }
+
+        #[ink::test]
+        fn relock_should_extend_the_lock() {
+            fund_default_accounts();
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(staking_contract_instance.set_lock_period(10), Ok(()));
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+
+            for _ in 0..8 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(
+                staking_contract_instance.relock(1),
+                Err(StakingError::Other(
+                    "cannot shorten the current lock".to_owned()
+                ))
+            );
+            assert_eq!(staking_contract_instance.relock(20), Ok(()));
+
+            assert_eq!(staking_contract_instance.get_unlock_block(alice), Some(28));
+            assert_eq!(
+                staking_contract_instance.get_account_stake(alice),
+                10 * STAKE_SCALE
+            );
+            // rewards accrued before the relock are kept
+            assert_eq!(staking_contract_instance.rewards_for_user(alice), 8);
+
+            for _ in 0..12 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(
+                staking_contract_instance.unstake(10 * STAKE_SCALE),
+                Err(StakingError::UnstakeError(
+                    "stake is still locked".to_owned()
+                ))
+            );
+        }
+
+        #[ink::test]
+        fn lock_period_changes_should_be_grandfathered() {
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(staking_contract_instance.set_lock_period(10), Ok(()));
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
+            assert_eq!(
+                staking_contract_instance.get_unlock_block(accounts.alice),
+                Some(10)
+            );
+
+            assert_eq!(staking_contract_instance.set_lock_period(100), Ok(()));
+            assert_eq!(staking_contract_instance.get_lock_period(), 100);
+            assert_eq!(
+                staking_contract_instance.get_unlock_block(accounts.alice),
+                Some(10)
+            );
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
+            assert_eq!(
+                staking_contract_instance.get_unlock_block(accounts.bob),
+                Some(100)
+            );
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+            assert_eq!(
+                staking_contract_instance.unstake(10),
+                Err(StakingError::UnstakeError(
+                    "stake is still locked".to_owned()
+                ))
+            );
+
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.unstake(10), Ok(()));
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(
+                staking_contract_instance.unstake(10),
+                Err(StakingError::UnstakeError(
+                    "stake is still locked".to_owned()
+                ))
+            );
+        }
+
+        #[ink::test]
+        fn can_unstake_should_reflect_the_lock() {
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(staking_contract_instance.set_lock_period(10), Ok(()));
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
+
+            assert!(!staking_contract_instance.can_unstake(accounts.alice));
+            assert!(!staking_contract_instance.can_unstake(accounts.bob));
+
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert!(staking_contract_instance.can_unstake(accounts.alice));
+            assert!(!staking_contract_instance.can_unstake(accounts.bob));
+        }
+
+        #[ink::test]
+        fn unstake_must_trigger_reward_claiming() {
+            fund_default_accounts();
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+            let initial_balance =
+                get_account_balance::<ink_env::DefaultEnvironment>(alice).unwrap();
+
+            let mut staking_contract_instance = Staking::new(1000);
+
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+            assert_eq!(
+                staking_contract_instance.get_account_stake(alice),
+                10 * STAKE_SCALE
+            );
+
+            for _ in 0..5 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+
+            let to_be_claimed = staking_contract_instance.rewards_for_user(alice);
+            assert_eq!(5, to_be_claimed);
+
+            let unstake_result = staking_contract_instance.unstake(10 * STAKE_SCALE);
+            assert_eq!(Ok(()), unstake_result);
+
+            let to_be_claimed = staking_contract_instance.rewards_for_user(alice);
+            assert_eq!(0, to_be_claimed);
+
+            let alice_balance = get_account_balance::<ink_env::DefaultEnvironment>(alice).unwrap();
+            assert_eq!(
+                initial_balance + 10 * STAKE_SCALE + 100 + 10 * STAKE_SCALE + 5,
+                alice_balance
+            );
+        }
+
+        #[ink::test]
+        fn emission_cap_should_scale_rewards() {
+            fund_default_accounts();
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(
+                staking_contract_instance.set_max_emission_per_block(Some(10)),
+                Ok(())
+            );
+
+            // uncapped, alice would accrue 10 and bob 30 per block
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 100 * STAKE_SCALE);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 300 * STAKE_SCALE);
+
+            for _ in 0..4 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+
+            // the aggregate of 40 per block is scaled down to the cap of 10
+            assert_eq!(
+                staking_contract_instance.rewards_for_user(accounts.alice),
+                10
+            );
+            assert_eq!(staking_contract_instance.rewards_for_user(accounts.bob), 30);
+        }
+
+        #[ink::test]
+        fn emission_cap_should_only_count_accruing_weight() {
+            fund_default_accounts();
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(
+                staking_contract_instance.set_max_emission_per_block(Some(15)),
+                Ok(())
+            );
+            assert_eq!(
+                staking_contract_instance.set_multiplier(accounts.alice, 20_000),
+                Ok(())
+            );
+
+            // the 2x boost makes alice accrue 20 per block, above the cap
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 100 * STAKE_SCALE);
+            assert_eq!(
+                staking_contract_instance.total_reward_weight,
+                200 * STAKE_SCALE
+            );
+            // bob does not accrue, so his stake does not take a share of the cap
+            assert_eq!(
+                staking_contract_instance.freeze_accrual(accounts.bob),
+                Ok(())
+            );
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 100 * STAKE_SCALE);
+            assert_eq!(
+                staking_contract_instance.total_reward_weight,
+                200 * STAKE_SCALE
+            );
+
+            for _ in 0..4 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(
+                staking_contract_instance.rewards_for_user(accounts.alice),
+                60
+            );
+            assert_eq!(staking_contract_instance.rewards_for_user(accounts.bob), 0);
+
+            // once unfrozen bob counts again and the two share the cap 2:1, without alice
+            // having to be settled
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+            assert_eq!(
+                staking_contract_instance.unfreeze_accrual(accounts.bob),
+                Ok(())
+            );
+            assert_eq!(
+                staking_contract_instance.total_reward_weight,
+                300 * STAKE_SCALE
+            );
+            for _ in 0..4 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(
+                staking_contract_instance.rewards_for_user(accounts.alice),
+                100
+            );
+            assert_eq!(staking_contract_instance.rewards_for_user(accounts.bob), 20);
+            assert_eq!(
+                staking_contract_instance
+                    .stake_positions
+                    .get(accounts.alice)
+                    .unwrap()
+                    .last_action_block,
+                0
+            );
+        }
+
+        #[ink::test]
+        fn setting_emission_cap_should_be_owner_only() {
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(
+                staking_contract_instance.set_max_emission_per_block(Some(10)),
+                Err(StakingError::Other(
+                    "only the owner can perform this action".to_owned()
+                ))
+            );
+            assert_eq!(staking_contract_instance.get_max_emission_per_block(), None);
+        }
+
+        #[ink::test]
+        fn early_unstake_should_forfeit_part_of_the_reward() {
+            fund_default_accounts();
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(
+                staking_contract_instance.set_early_unstake_penalty(5000, 10),
+                Ok(())
+            );
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+
+            for _ in 0..5 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.rewards_for_user(alice), 5);
+
+            let alice_balance = get_account_balance::<ink_env::DefaultEnvironment>(alice).unwrap();
+            assert_eq!(staking_contract_instance.unstake(10 * STAKE_SCALE), Ok(()));
+
+            // half of the reward of 5, rounded down, stays in the pool
+            let alice_new_balance =
+                get_account_balance::<ink_env::DefaultEnvironment>(alice).unwrap();
+            assert_eq!(alice_new_balance, alice_balance + 10 * STAKE_SCALE + 3);
+            assert_eq!(staking_contract_instance.get_reward_pool(), 97);
+
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            assert_claimed_event(&emitted_events[2], &alice, 3);
+        }
+
+        #[ink::test]
+        fn early_unstake_penalty_should_be_waived_until_the_waiver_ends() {
+            fund_default_accounts();
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            set_contract_account();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(
+                staking_contract_instance.set_early_unstake_penalty(5000, 100),
+                Ok(())
+            );
+            assert_eq!(
+                staking_contract_instance.set_penalty_waiver_until(3),
+                Ok(())
+            );
+            assert_eq!(staking_contract_instance.get_penalty_waiver_until(), 3);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 100 * STAKE_SCALE);
+
+            for _ in 0..2 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+
+            // still within the waiver, the full reward of 20 is paid
+            let alice_balance = get_account_balance::<ink_env::DefaultEnvironment>(alice).unwrap();
+            assert_eq!(staking_contract_instance.unstake(50 * STAKE_SCALE), Ok(()));
+            assert_eq!(
+                get_account_balance::<ink_env::DefaultEnvironment>(alice).unwrap(),
+                alice_balance + 50 * STAKE_SCALE + 20
+            );
+
+            for _ in 0..2 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+
+            // the waiver is over, half of the reward of 10 is forfeited
+            let alice_balance = get_account_balance::<ink_env::DefaultEnvironment>(alice).unwrap();
+            assert_eq!(staking_contract_instance.unstake(50 * STAKE_SCALE), Ok(()));
+            assert_eq!(
+                get_account_balance::<ink_env::DefaultEnvironment>(alice).unwrap(),
+                alice_balance + 50 * STAKE_SCALE + 5
+            );
+            assert_eq!(staking_contract_instance.get_reward_pool(), 75);
+        }
+
+        #[ink::test]
+        fn mature_unstake_should_pay_the_full_reward() {
+            fund_default_accounts();
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(
+                staking_contract_instance.set_early_unstake_penalty(5000, 10),
+                Ok(())
+            );
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+
+            let alice_balance = get_account_balance::<ink_env::DefaultEnvironment>(alice).unwrap();
+            assert_eq!(staking_contract_instance.unstake(10 * STAKE_SCALE), Ok(()));
+
+            let alice_new_balance =
+                get_account_balance::<ink_env::DefaultEnvironment>(alice).unwrap();
+            assert_eq!(alice_new_balance, alice_balance + 10 * STAKE_SCALE + 10);
+            assert_eq!(staking_contract_instance.get_reward_pool(), 90);
+        }
 
-        fn assert_staked_event(
-            event: &EmittedEvent,
-            expected_user: &AccountId,
-            expected_amount: Balance,
-        ) {
-            let decoded_event = <Event as scale::Decode>::decode(&mut &event.data[..])
-                .expect("encountered invalid contract event data buffer");
-            if let Event::Staked(Staked { user, amount }) = decoded_event {
-                assert_eq!(user, *expected_user);
-                assert_eq!(amount, expected_amount);
-            } else {
-                panic!("encountered unexpected event kind: expected a Staked event")
+        #[ink::test]
+        fn rewards_should_only_accrue_above_the_minimum_stake() {
+            fund_default_accounts();
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(
+                staking_contract_instance.set_reward_min_stake(50 * STAKE_SCALE),
+                Ok(())
+            );
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 100 * STAKE_SCALE);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+
+            for _ in 0..2 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.rewards_for_user(alice), 20);
+
+            // dropping below the threshold stops the accrual
+            assert_eq!(staking_contract_instance.unstake(60 * STAKE_SCALE), Ok(()));
+            for _ in 0..2 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.rewards_for_user(alice), 0);
+
+            // topping back up resumes it
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 20 * STAKE_SCALE);
+            for _ in 0..2 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
             }
+            assert_eq!(staking_contract_instance.rewards_for_user(alice), 12);
         }
 
-        fn assert_unstaked_event(
-            event: &EmittedEvent,
-            expected_user: &AccountId,
-            expected_amount: Balance,
-        ) {
-            let decoded_event = <Event as scale::Decode>::decode(&mut &event.data[..])
-                .expect("encountered invalid contract event data buffer");
-            if let Event::Unstaked(Unstaked { user, amount }) = decoded_event {
-                assert_eq!(user, *expected_user);
-                assert_eq!(amount, expected_amount);
-            } else {
-                panic!("encountered unexpected event kind: expected a Unstaked event")
+        #[ink::test]
+        fn tiny_rates_should_accrue_over_frequent_checkpoints() {
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            ink_env::test::set_account_balance::<ink_env::DefaultEnvironment>(
+                alice,
+                10_000_000_000,
+            );
+
+            // 0.01% a year accrues less than a basis point of a unit per block
+            let mut staking_contract_instance = Staking::new(1);
+            assert_eq!(
+                staking_contract_instance.set_accrual_basis(AccrualBasis::Timestamp),
+                Ok(())
+            );
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 4_000_000_000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+
+            // claiming every block moves the checkpoint every time
+            for _ in 0..20_000 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+                assert_eq!(staking_contract_instance.claim_reward(), Ok(()));
             }
+
+            assert_eq!(staking_contract_instance.get_total_rewards_paid(), 1);
         }
 
-        fn assert_claimed_event(
-            event: &EmittedEvent,
-            expected_user: &AccountId,
-            expected_amount: Balance,
-        ) {
-            let decoded_event = <Event as scale::Decode>::decode(&mut &event.data[..])
-                .expect("encountered invalid contract event data buffer");
-            if let Event::Claimed(Claimed { user, amount }) = decoded_event {
-                assert_eq!(user, *expected_user);
-                assert_eq!(amount, expected_amount);
-            } else {
-                panic!("encountered unexpected event kind: expected a Claimed event")
+        #[ink::test]
+        fn rates_below_a_basis_point_should_accrue() {
+            fund_default_accounts();
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(0);
+            assert_eq!(
+                staking_contract_instance.set_apy_scaled(u128::from(MAX_APY) * PRECISION + 1),
+                Err(StakingError::Other("APY cannot exceed MAX_APY".to_owned()))
+            );
+            // a hundredth of a basis point a year
+            assert_eq!(
+                staking_contract_instance.set_apy_scaled(PRECISION / 100),
+                Ok(())
+            );
+            assert_eq!(staking_contract_instance.get_apy(), 0);
+            assert_eq!(staking_contract_instance.get_apy_scaled(), PRECISION / 100);
+            assert_eq!(staking_contract_instance.get_config().apy, PRECISION / 100);
+
+            // 10_000 * STAKE_SCALE earns a hundredth of a unit per block
+            let _ =
+                ink_env::pay_with_call!(staking_contract_instance.stake(), 10_000 * STAKE_SCALE);
+            for _ in 0..1000 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
             }
+            assert_eq!(staking_contract_instance.rewards_for_user(alice), 10);
         }
 
         #[ink::test]
-        fn deployment_works() {
-            let staking = Staking::new(1000);
-            assert_eq!(staking.apy, 1000);
-            assert_eq!(staking.staked_addresses, Vec::default());
+        fn rewards_should_match_the_analytic_value_over_a_long_horizon() {
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+            fund_default_accounts();
+
+            // a multiplier of 1.0001x makes every block earn 0.99999999 units
+            let mut staking_contract_instance = Staking::new(9999);
+            assert_eq!(
+                staking_contract_instance.set_multiplier(alice, 10_001),
+                Ok(())
+            );
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 20_000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), STAKE_SCALE);
+
+            for _ in 0..12_000 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+                assert_eq!(staking_contract_instance.claim_reward(), Ok(()));
+            }
+
+            // 12_000 * 0.99999999 = 11_999.99988
+            assert_eq!(staking_contract_instance.get_total_rewards_paid(), 11_999);
         }
 
         #[ink::test]
-        fn first_time_staking_should_work() {
+        fn huge_stakes_should_accrue_and_unstake() {
             let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
             ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+            set_contract_account();
+            ink_env::test::set_account_balance::<ink_env::DefaultEnvironment>(
+                alice,
+                10u128.pow(31),
+            );
 
+            // scaled by PRECISION the reward no longer fits in a u128
+            let stake = 10u128.pow(30);
             let mut staking_contract_instance = Staking::new(1000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 2 * stake);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), stake);
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+
+            // 10% a year over 10 blocks
+            let reward = stake / Balance::from(BLOCKS_PER_YEAR);
+            assert_eq!(staking_contract_instance.rewards_for_user(alice), reward);
+            assert_eq!(staking_contract_instance.unstake(stake), Ok(()));
+            assert_eq!(staking_contract_instance.get_total_rewards_paid(), reward);
             assert_eq!(staking_contract_instance.get_account_stake(alice), 0);
+        }
+
+        #[ink::test]
+        fn reward_rate_info_should_match_configuration() {
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new_with_weighting(500, WeightMode::Sqrt);
+            assert_eq!(
+                staking_contract_instance.reward_rate_info(),
+                (500 * PRECISION, BLOCKS_PER_YEAR, WeightMode::Sqrt)
+            );
+
+            assert_eq!(staking_contract_instance.set_apy(2000), Ok(()));
+            assert_eq!(
+                staking_contract_instance.reward_rate_info(),
+                (2000 * PRECISION, BLOCKS_PER_YEAR, WeightMode::Sqrt)
+            );
+
+            // the rate shown is the one actually paid above the target TVL
+            assert_eq!(staking_contract_instance.set_target_tvl(100), Ok(()));
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 200);
+            assert_eq!(
+                staking_contract_instance.reward_rate_info(),
+                (1000 * PRECISION, BLOCKS_PER_YEAR, WeightMode::Sqrt)
+            );
+
+            // the rate is quoted per year whatever the accrual basis
+            assert_eq!(
+                staking_contract_instance.set_accrual_basis(AccrualBasis::Timestamp),
+                Ok(())
+            );
+            assert_eq!(
+                staking_contract_instance.reward_rate_info(),
+                (1000 * PRECISION, BLOCKS_PER_YEAR, WeightMode::Sqrt)
+            );
+        }
+
+        #[ink::test]
+        fn timestamp_accrual_should_scale_with_elapsed_time() {
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+            let stake = Balance::from(SECONDS_PER_YEAR) * 1_000_000;
+            ink_env::test::set_account_balance::<ink_env::DefaultEnvironment>(alice, 2 * stake);
+
+            // 100% a year
+            let mut staking_contract_instance = Staking::new(10_000);
+            assert_eq!(
+                staking_contract_instance.set_accrual_basis(AccrualBasis::Timestamp),
+                Ok(())
+            );
+            assert_eq!(
+                staking_contract_instance.get_accrual_basis(),
+                AccrualBasis::Timestamp
+            );
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), stake);
+
+            // the off-chain environment advances the timestamp by 6 milliseconds per block, and
+            // this stake earns 1_000 per millisecond
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            assert_eq!(staking_contract_instance.rewards_for_user(alice), 6_000);
+
+            for _ in 0..2 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.rewards_for_user(alice), 18_000);
+        }
+
+        #[ink::test]
+        fn timestamp_accrual_should_stop_at_the_reward_end_block() {
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+            let stake = Balance::from(SECONDS_PER_YEAR) * 1_000_000;
+            ink_env::test::set_account_balance::<ink_env::DefaultEnvironment>(alice, 2 * stake);
+
+            let mut staking_contract_instance = Staking::new(10_000);
+            assert_eq!(
+                staking_contract_instance.set_accrual_basis(AccrualBasis::Timestamp),
+                Ok(())
+            );
+            assert_eq!(
+                staking_contract_instance.set_reward_end_block(Some(2)),
+                Ok(())
+            );
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), stake);
+
+            // only the 12 milliseconds of the first two blocks earn, 1_000 per millisecond
+            for _ in 0..5 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.rewards_for_user(alice), 12_000);
+        }
+
+        #[ink::test]
+        fn timestamp_accrual_should_compound() {
+            let mut staking_contract_instance = Staking::new(10_000);
+            assert_eq!(staking_contract_instance.set_compound_frequency(12), Ok(()));
+
+            // 100% a year compounded monthly: 1000 * ((13 / 12)^12 - 1)
+            let millis = MILLIS_PER_YEAR;
+            let compounded = staking_contract_instance
+                .compounded_reward(
+                    U256::from_u128(1000 * PRECISION * Balance::from(millis)),
+                    millis,
+                    true,
+                )
+                .and_then(|reward| reward.checked_div_u128(PRECISION * Balance::from(millis)));
+            assert_eq!(compounded, Some(1613));
+        }
+
+        #[ink::test]
+        fn compound_accrual_should_not_be_below_simple_accrual() {
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(10_000);
+            assert_eq!(staking_contract_instance.set_compound_frequency(2), Ok(()));
+            assert_eq!(staking_contract_instance.get_compound_frequency(), 2);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 1000);
+
+            for _ in 0..BLOCKS_PER_YEAR / 4 * 3 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+
+            // 100% a year compounded every half year, the last quarter is not a full period yet:
+            // 1000 * (1.5 * 1.25 - 1) instead of 1000 * 0.75
+            let simple_reward = safe_reward(1000, 10_000, u64::from(BLOCKS_PER_YEAR / 4 * 3));
+            assert_eq!(simple_reward, Ok(750));
+            assert_eq!(staking_contract_instance.rewards_for_user(alice), 875);
+
+            assert_eq!(
+                staking_contract_instance.set_compound_frequency(BLOCKS_PER_YEAR + 1),
+                Err(StakingError::Other(
+                    "cannot compound more than once per block".to_owned()
+                ))
+            );
+        }
+
+        #[ink::test]
+        fn compounding_should_not_overflow_over_a_full_year() {
+            let mut staking_contract_instance = Staking::new(MAX_APY);
+            assert_eq!(
+                staking_contract_instance.set_compound_frequency(BLOCKS_PER_YEAR),
+                Ok(())
+            );
+
+            // 1000% a year compounded every block grows the stake close to e^10 times
+            let simple_reward = safe_reward(1000, MAX_APY, BLOCKS_PER_YEAR.into()).unwrap();
+            assert_eq!(simple_reward, 10_000);
+            let compounded = staking_contract_instance
+                .compounded_reward(
+                    U256::from_u128(simple_reward * PRECISION),
+                    BLOCKS_PER_YEAR.into(),
+                    false,
+                )
+                .and_then(|reward| reward.checked_div_u128(PRECISION));
+            // 1000 * ((1 + 10 / BLOCKS_PER_YEAR)^BLOCKS_PER_YEAR - 1), just below
+            // 1000 * (e^10 - 1) = 22_025_465 as every step rounds down
+            assert_eq!(compounded, Some(22_025_122));
+        }
+
+        #[ink::test]
+        fn lifetime_rewards_should_plateau_at_the_cap() {
+            fund_default_accounts();
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            // at most 0.01% of the stake in rewards: 20 * STAKE_SCALE / 10_000 = 10_512
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(
+                staking_contract_instance.set_max_reward_multiple_bps(Some(1)),
+                Ok(())
+            );
+            assert_eq!(
+                staking_contract_instance.get_max_reward_multiple_bps(),
+                Some(1)
+            );
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 20_000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 20 * STAKE_SCALE);
+
+            for _ in 0..3 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.claim_reward(), Ok(()));
+            assert_eq!(staking_contract_instance.get_total_rewards_paid(), 6);
+
+            for _ in 0..5_500 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.rewards_for_user(alice), 10_506);
+            assert_eq!(staking_contract_instance.claim_reward(), Ok(()));
+
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.rewards_for_user(alice), 0);
+            assert_eq!(staking_contract_instance.get_total_rewards_paid(), 10_512);
+        }
+
+        #[ink::test]
+        fn rewards_should_stop_at_the_end_block() {
+            fund_default_accounts();
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(
+                staking_contract_instance.set_reward_end_block(Some(5)),
+                Ok(())
+            );
+            assert_eq!(staking_contract_instance.get_reward_end_block(), Some(5));
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+
+            for _ in 0..3 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.rewards_for_user(alice), 3);
+
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.rewards_for_user(alice), 5);
+
+            // stakes made after the end do not accrue at all
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            assert_eq!(staking_contract_instance.rewards_for_user(alice), 5);
+        }
+
+        #[ink::test]
+        fn frozen_rewards_should_not_accrue() {
+            fund_default_accounts();
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+
+            for _ in 0..3 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.set_rewards_frozen(true), Ok(()));
+            assert!(staking_contract_instance.is_rewards_frozen());
+
+            // blocks elapsed during the freeze earn nothing
+            for _ in 0..5 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.rewards_for_user(alice), 3);
+
+            // accrual continues from the unfreeze block
+            assert_eq!(staking_contract_instance.set_rewards_frozen(false), Ok(()));
+            for _ in 0..2 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.rewards_for_user(alice), 5);
+
+            let alice_balance = get_account_balance::<ink_env::DefaultEnvironment>(alice).unwrap();
+            assert_eq!(staking_contract_instance.claim_reward(), Ok(()));
+            assert_eq!(
+                get_account_balance::<ink_env::DefaultEnvironment>(alice).unwrap(),
+                alice_balance + 5
+            );
+            assert_eq!(staking_contract_instance.rewards_for_user(alice), 0);
+        }
+
+        #[ink::test]
+        fn zero_claims_should_be_skipped_when_enabled() {
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+
+            let mut staking_contract_instance = Staking::new_with_skip_zero_claims(1000, true);
+            assert!(staking_contract_instance.get_skip_zero_claims());
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
+
+            let position = staking_contract_instance.stake_positions.get(alice);
+            let checkpoints = staking_contract_instance.reward_checkpoints.get(alice);
+            let activity_counts = staking_contract_instance.get_activity_counts();
+            let event_count = ink_env::test::recorded_events().count();
+
+            assert_eq!(staking_contract_instance.claim_reward(), Ok(()));
+            assert_eq!(
+                staking_contract_instance.stake_positions.get(alice),
+                position
+            );
+            assert_eq!(
+                staking_contract_instance.reward_checkpoints.get(alice),
+                checkpoints
+            );
+            assert_eq!(
+                staking_contract_instance.get_activity_counts(),
+                activity_counts
+            );
+            assert_eq!(ink_env::test::recorded_events().count(), event_count);
+            // a regular claim would have recorded the claim block
+            assert_eq!(
+                staking_contract_instance
+                    .stake_positions
+                    .get(alice)
+                    .and_then(|position| position.last_claim_block),
+                None
+            );
+        }
+
+        #[ink::test]
+        fn reward_weight_should_include_the_boost() {
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(
+                staking_contract_instance.set_multiplier(accounts.alice, 20_000),
+                Ok(())
+            );
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
+
+            assert_eq!(staking_contract_instance.reward_weight(accounts.alice), 20);
+            assert_eq!(staking_contract_instance.reward_weight(accounts.bob), 0);
+        }
+
+        #[ink::test]
+        fn multiplier_should_boost_rewards() {
+            fund_default_accounts();
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(
+                staking_contract_instance.set_multiplier(accounts.alice, 20_000),
+                Ok(())
+            );
+            assert_eq!(
+                staking_contract_instance.get_multiplier(accounts.alice),
+                20_000
+            );
+            assert_eq!(
+                staking_contract_instance.get_multiplier(accounts.bob),
+                DEFAULT_MULTIPLIER_BPS
+            );
 
-            let stake = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
-            assert_eq!(stake, Ok(()));
-            assert_eq!(staking_contract_instance.get_account_stake(alice), 10);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
 
-            // contract now has 10 coins more
-            let contract_balance = get_account_balance::<ink_env::DefaultEnvironment>(
-                staking_contract_instance.env().account_id(),
-            )
-            .unwrap();
-            assert_eq!(1000010, contract_balance);
+            for _ in 0..5 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
 
-            assert!(staking_contract_instance.staked_addresses.contains(&alice));
+            assert_eq!(
+                staking_contract_instance.rewards_for_user(accounts.alice),
+                10
+            );
+            assert_eq!(staking_contract_instance.rewards_for_user(accounts.bob), 5);
 
-            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
-            assert_eq!(1, emitted_events.len());
-            assert_staked_event(&emitted_events[0], &alice, 10);
+            assert_eq!(
+                staking_contract_instance.set_multiplier(accounts.bob, 20_000),
+                Err(StakingError::Other(
+                    "only the owner can perform this action".to_owned()
+                ))
+            );
         }
 
         #[ink::test]
-        fn increasing_existing_stake_should_work() {
-            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
-            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+        fn reward_breakdown_should_split_base_and_bonus() {
+            fund_default_accounts();
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
 
             let mut staking_contract_instance = Staking::new(1000);
-            assert_eq!(staking_contract_instance.get_account_stake(alice), 0);
+            assert_eq!(
+                staking_contract_instance.set_multiplier(accounts.alice, 15_000),
+                Ok(())
+            );
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
 
-            let stake = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
-            assert_eq!(stake, Ok(()));
-            assert_eq!(staking_contract_instance.get_account_stake(alice), 10);
+            for _ in 0..4 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
 
-            let stake_again = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
-            assert_eq!(stake_again, Ok(()));
-            assert_eq!(staking_contract_instance.get_account_stake(alice), 20);
+            let (base_reward, bonus_reward) =
+                staking_contract_instance.reward_breakdown(accounts.alice);
+            assert_eq!((base_reward, bonus_reward), (4, 2));
+            assert_eq!(
+                base_reward + bonus_reward,
+                staking_contract_instance.rewards_for_user(accounts.alice)
+            );
+
+            assert_eq!(
+                staking_contract_instance.reward_breakdown(accounts.bob),
+                (4, 0)
+            );
+            assert_eq!(
+                staking_contract_instance.reward_breakdown(accounts.charlie),
+                (0, 0)
+            );
         }
 
         #[ink::test]
-        #[should_panic(expected = "Must stake more than 0")]
-        fn staking_zero_should_not_be_allowed() {
+        fn average_stake_should_be_time_weighted() {
             let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
             ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
 
             let mut staking_contract_instance = Staking::new(1000);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
 
-            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 0);
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(staking_contract_instance.average_stake(alice, 10), 10);
+
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+
+            // 10 blocks at 10 and 10 blocks at 20
+            assert_eq!(staking_contract_instance.average_stake(alice, 20), 15);
+            assert_eq!(staking_contract_instance.average_stake(alice, 10), 20);
+            // blocks before the first stake count as zero
+            assert_eq!(staking_contract_instance.average_stake(alice, 40), 7);
         }
 
         #[ink::test]
-        fn claiming_should_work() {
+        fn boost_should_decay_without_interaction() {
+            fund_default_accounts();
             let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
             ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
-            let alice_balance = get_account_balance::<ink_env::DefaultEnvironment>(alice).unwrap();
-            assert_eq!(alice_balance, 1000000);
 
             let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(
+                staking_contract_instance.set_multiplier(alice, 20_000),
+                Ok(())
+            );
+            assert_eq!(staking_contract_instance.set_boost_decay_blocks(10), Ok(()));
 
-            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
-
-            assert_eq!(staking_contract_instance.get_account_stake(alice), 10);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+            assert_eq!(staking_contract_instance.current_boost(alice), 20_000);
 
             for _ in 0..5 {
                 ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
             }
+            assert_eq!(staking_contract_instance.current_boost(alice), 15_000);
 
-            let to_be_claimed = staking_contract_instance.rewards_for_user(alice);
-            assert_eq!(5, to_be_claimed);
-
-            let claim = staking_contract_instance.claim_reward();
-            assert_eq!(claim, Ok(()));
+            for _ in 0..5 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(
+                staking_contract_instance.current_boost(alice),
+                DEFAULT_MULTIPLIER_BPS
+            );
+            // the boost averaged 1.5x over the 10 blocks
+            assert_eq!(staking_contract_instance.rewards_for_user(alice), 15);
 
-            let alice_balance = get_account_balance::<ink_env::DefaultEnvironment>(alice).unwrap();
-            assert_eq!(alice_balance, 1000015);
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(
+                staking_contract_instance.current_boost(alice),
+                DEFAULT_MULTIPLIER_BPS
+            );
 
-            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
-            assert_eq!(2, emitted_events.len());
-            assert_staked_event(&emitted_events[0], &alice, 10);
-            assert_claimed_event(&emitted_events[1], &alice, 5);
+            // neither claiming nor relocking restores the boost, staking again does
+            assert_eq!(staking_contract_instance.claim_reward(), Ok(()));
+            assert_eq!(
+                staking_contract_instance.current_boost(alice),
+                DEFAULT_MULTIPLIER_BPS
+            );
+            assert_eq!(staking_contract_instance.relock(0), Ok(()));
+            assert_eq!(
+                staking_contract_instance.current_boost(alice),
+                DEFAULT_MULTIPLIER_BPS
+            );
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            assert_eq!(staking_contract_instance.rewards_for_user(alice), 1);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            assert_eq!(staking_contract_instance.current_boost(alice), 20_000);
         }
 
         #[ink::test]
-        fn claiming_while_not_staked_should_not_work() {
+        fn owner_settlements_should_not_restart_the_boost_decay() {
+            fund_default_accounts();
             let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
             ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
 
             let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(
+                staking_contract_instance.set_multiplier(alice, 20_000),
+                Ok(())
+            );
+            assert_eq!(staking_contract_instance.set_boost_decay_blocks(10), Ok(()));
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
 
-            let claim = staking_contract_instance.claim_reward();
+            for _ in 0..5 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            // settles every position
+            assert_eq!(staking_contract_instance.set_apy(1000), Ok(()));
+            assert_eq!(staking_contract_instance.current_boost(alice), 15_000);
+
+            for _ in 0..5 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            // 1.75x over the first 5 blocks, 1.25x over the last 5
+            assert_eq!(staking_contract_instance.rewards_for_user(alice), 15);
             assert_eq!(
-                claim,
-                Err(StakingError::ClaimingRewardError(
-                    "user doesnt seem to have a stake".to_owned()
+                staking_contract_instance.current_boost(alice),
+                DEFAULT_MULTIPLIER_BPS
+            );
+
+            // a long window does not overflow
+            assert_eq!(
+                staking_contract_instance.set_boost_decay_blocks(500_000),
+                Ok(())
+            );
+            assert_eq!(staking_contract_instance.current_boost(alice), 19_999);
+        }
+
+        #[ink::test]
+        fn safe_reward_should_work() {
+            let blocks_per_year = u64::from(BLOCKS_PER_YEAR);
+            assert_eq!(safe_reward(10 * STAKE_SCALE, 1000, 10), Ok(10));
+            assert_eq!(safe_reward(Balance::MAX, 0, 10), Ok(0));
+            assert_eq!(
+                safe_reward(Balance::MAX, 1000, 1),
+                Ok(6_474_169_842_483_608_513_382_317_492_994)
+            );
+            // the products overflow a u128, the rewards do not
+            assert_eq!(
+                safe_reward(Balance::MAX, 1, 2),
+                Ok(12_948_339_684_967_217_026_764_634_985)
+            );
+            assert_eq!(
+                safe_reward(Balance::MAX, 10_000, blocks_per_year),
+                Ok(Balance::MAX)
+            );
+            assert_eq!(
+                safe_reward(Balance::MAX, 10_000, 2 * blocks_per_year),
+                Err(StakingError::Other(
+                    "reward calculation overflowed".to_owned()
                 ))
-            )
+            );
         }
 
         #[ink::test]
-        fn unstake_should_work() {
+        fn u256_should_divide_back_wide_products() {
+            let product = U256::full_mul(Balance::MAX, Balance::MAX);
+            assert_eq!(
+                product,
+                U256 {
+                    hi: Balance::MAX - 1,
+                    lo: 1
+                }
+            );
+            assert_eq!(product.checked_div_u128(Balance::MAX), Some(Balance::MAX));
+            assert_eq!(product.checked_div_u128(Balance::MAX - 1), None);
+            assert_eq!(
+                U256::from_u128(Balance::MAX)
+                    .checked_mul_u128(3)
+                    .and_then(|product| product.checked_div_u128(4)),
+                Some(Balance::MAX / 4 * 3 + 2)
+            );
+            assert_eq!(U256::from_u128(1).checked_div_u128(0), None);
+            assert_eq!(
+                product.div_rem_u128(1 << 64),
+                Some((
+                    U256 {
+                        hi: Balance::MAX >> 64,
+                        lo: (Balance::MAX - 1) << 64
+                    },
+                    1
+                ))
+            );
+            assert_eq!(product.checked_mul_u128(2), None);
+            assert_eq!(product.checked_mul(product), None);
+            assert_eq!(
+                U256::from_u128(Balance::MAX).checked_mul(U256::from_u128(Balance::MAX)),
+                Some(product)
+            );
+            assert_eq!(
+                U256 { hi: 1, lo: 0 }.checked_sub_u128(1),
+                Some(U256::from_u128(Balance::MAX))
+            );
+        }
+
+        #[ink::test]
+        fn reward_at_block_should_work() {
+            for (stake, apy, from_block, to_block, reward) in [
+                (10 * STAKE_SCALE, 1000, 0, 5, 5),
+                (10 * STAKE_SCALE, 1000, 5, 10, 5),
+                (10 * STAKE_SCALE, 1000, 5, 5, 0),
+                (10 * STAKE_SCALE, 1000, 10, 5, 0),
+                (0, 1000, 0, 100, 0),
+                (10 * STAKE_SCALE, 0, 0, 100, 0),
+                (15 * STAKE_SCALE, 1000, 0, 1, 1),
+                (1000 * STAKE_SCALE, MAX_APY, 0, 3, 30_000),
+                (
+                    Balance::MAX / 20,
+                    MAX_APY,
+                    0,
+                    1,
+                    32_370_849_212_418_042_566_911_587_464_970,
+                ),
+                (Balance::MAX, MAX_APY, 0, BLOCKS_PER_YEAR, Balance::MAX),
+            ] {
+                assert_eq!(
+                    Staking::reward_at_block(stake, apy, from_block, to_block),
+                    reward
+                );
+            }
+        }
+
+        #[ink::test]
+        fn reward_at_block_should_match_accrual() {
+            fund_default_accounts();
             let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
             ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
 
-            let mut staking_contract_instance = Staking::new(1000);
-            assert_eq!(staking_contract_instance.get_account_stake(alice), 0);
-
-            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
-            assert_eq!(staking_contract_instance.get_account_stake(alice), 10);
+            let mut staking_contract_instance = Staking::new(1500);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 70 * STAKE_SCALE);
 
-            let unstake_result = staking_contract_instance.unstake(10);
-            assert_eq!(unstake_result, Ok(()));
-            assert_eq!(staking_contract_instance.get_account_stake(alice), 0);
+            for _ in 0..7 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
             assert_eq!(
-                staking_contract_instance.staked_addresses.contains(&alice),
-                false
+                staking_contract_instance.rewards_for_user(alice),
+                Staking::reward_at_block(70 * STAKE_SCALE, 1500, 0, 7)
             );
-
-            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
-            assert_eq!(2, emitted_events.len());
-            assert_staked_event(&emitted_events[0], &alice, 10);
-            assert_unstaked_event(&emitted_events[1], &alice, 10);
         }
 
         #[ink::test]
-        fn partial_unstake_should_work() {
-            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
-            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+        fn integer_sqrt_should_round_down() {
+            assert_eq!(integer_sqrt(0), 0);
+            assert_eq!(integer_sqrt(1), 1);
+            assert_eq!(integer_sqrt(99), 9);
+            assert_eq!(integer_sqrt(100), 10);
+            assert_eq!(integer_sqrt(u128::MAX), u64::MAX as u128);
+        }
 
-            let mut staking_contract_instance = Staking::new(1000);
-            assert_eq!(staking_contract_instance.get_account_stake(alice), 0);
+        fn rewards_for_small_and_large_stakers(weighting: WeightMode) -> (Balance, Balance) {
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            fund_default_accounts();
+            let mut staking_contract_instance = Staking::new_with_weighting(1000, weighting);
+            assert_eq!(staking_contract_instance.get_weighting(), weighting);
 
-            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
-            assert_eq!(staking_contract_instance.get_account_stake(alice), 10);
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+            let _ = ink_env::pay_with_call!(
+                staking_contract_instance.stake(),
+                10_000 * STAKE_SCALE * STAKE_SCALE
+            );
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            let _ = ink_env::pay_with_call!(
+                staking_contract_instance.stake(),
+                100 * STAKE_SCALE * STAKE_SCALE
+            );
 
-            let unstake_result = staking_contract_instance.unstake(5);
-            assert_eq!(unstake_result, Ok(()));
-            assert_eq!(staking_contract_instance.get_account_stake(alice), 5);
-            assert!(staking_contract_instance.staked_addresses.contains(&alice));
+            for _ in 0..10 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
 
-            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
-            assert_eq!(2, emitted_events.len());
-            assert_staked_event(&emitted_events[0], &alice, 10);
-            assert_unstaked_event(&emitted_events[1], &alice, 5);
+            (
+                staking_contract_instance.rewards_for_user(accounts.bob),
+                staking_contract_instance.rewards_for_user(accounts.alice),
+            )
         }
 
         #[ink::test]
-        #[should_panic(expected = "Must unstake more than 0")]
-        fn unstake_zero_not_allowed() {
-            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
-            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
-
-            let mut staking_contract_instance = Staking::new(1000);
-            assert_eq!(staking_contract_instance.get_account_stake(alice), 0);
-
-            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
-            assert_eq!(staking_contract_instance.get_account_stake(alice), 10);
+        fn linear_weighting_should_be_proportional_to_stake() {
+            let (small, large) = rewards_for_small_and_large_stakers(WeightMode::Linear);
+            assert_eq!(small, 100 * STAKE_SCALE);
+            assert_eq!(large, 10_000 * STAKE_SCALE);
+        }
 
-            let _ = staking_contract_instance.unstake(0);
+        #[ink::test]
+        fn sqrt_weighting_should_dampen_large_stakes() {
+            // 100x the stake only earns 10x the reward
+            let (small, large) = rewards_for_small_and_large_stakers(WeightMode::Sqrt);
+            assert_eq!(small, 10);
+            assert_eq!(large, 100);
         }
 
         #[ink::test]
-        fn unstake_more_than_staked_should_not_work() {
+        fn total_value_should_include_unclaimed_rewards() {
+            fund_default_accounts();
             let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
             ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
 
             let mut staking_contract_instance = Staking::new(1000);
-            assert_eq!(staking_contract_instance.get_account_stake(alice), 0);
+            assert_eq!(staking_contract_instance.total_value(alice), 0);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
 
-            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
-            assert_eq!(staking_contract_instance.get_account_stake(alice), 10);
+            for _ in 0..5 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
 
-            let unstake = staking_contract_instance.unstake(11);
             assert_eq!(
-                unstake,
-                Err(StakingError::UnstakeError(
-                    "unstake amount cannot be greater than staked amount".to_owned()
-                ))
-            )
-        }
-
-        #[ink::test]
-        fn unstake_should_work_iff_user_has_staked() {
-            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
-            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+                staking_contract_instance.total_value(alice),
+                staking_contract_instance.get_account_stake(alice)
+                    + staking_contract_instance.rewards_for_user(alice)
+            );
+            assert_eq!(
+                staking_contract_instance.total_value(alice),
+                10 * STAKE_SCALE + 5
+            );
 
-            let mut staking_contract_instance = Staking::new(1000);
-            let unstake = staking_contract_instance.unstake(1);
+            assert_eq!(staking_contract_instance.compound(), Ok(()));
             assert_eq!(
-                unstake,
-                Err(StakingError::UnstakeError(
-                    "can only unstake if user has already staked".to_owned()
-                ))
-            )
+                staking_contract_instance.get_account_stake(alice),
+                10 * STAKE_SCALE + 5
+            );
         }
 
         #[ink::test]
-        fn unstake_must_trigger_reward_claiming() {
-            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
-            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
+        fn blocks_until_next_reward_unit_should_work() {
+            fund_default_accounts();
+            let accounts = default_accounts::<ink_env::DefaultEnvironment>();
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
 
             let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(
+                staking_contract_instance.blocks_until_next_reward_unit(accounts.alice),
+                None
+            );
 
-            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10);
-            assert_eq!(staking_contract_instance.get_account_stake(alice), 10);
+            // a stake of `STAKE_SCALE` at 1000 bps earns one unit every 10 blocks
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), STAKE_SCALE);
+            assert_eq!(
+                staking_contract_instance.blocks_until_next_reward_unit(accounts.alice),
+                Some(10)
+            );
 
-            for _ in 0..5 {
+            for _ in 0..3 {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+            assert_eq!(
+                staking_contract_instance.rewards_for_user(accounts.alice),
+                0
+            );
+            assert_eq!(
+                staking_contract_instance.blocks_until_next_reward_unit(accounts.alice),
+                Some(7)
+            );
+
+            for _ in 0..7 {
                 ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
             }
+            assert_eq!(
+                staking_contract_instance.rewards_for_user(accounts.alice),
+                1
+            );
+            assert_eq!(
+                staking_contract_instance.blocks_until_next_reward_unit(accounts.alice),
+                Some(10)
+            );
 
-            let to_be_claimed = staking_contract_instance.rewards_for_user(alice);
-            assert_eq!(5, to_be_claimed);
+            // a stake of `10 * STAKE_SCALE` earns at least one unit every block
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 10 * STAKE_SCALE);
+            assert_eq!(
+                staking_contract_instance.blocks_until_next_reward_unit(accounts.bob),
+                Some(0)
+            );
+        }
 
-            let unstake_result = staking_contract_instance.unstake(10);
-            assert_eq!(Ok(()), unstake_result);
+        #[ink::test]
+        fn reward_projections_should_follow_the_timestamp_accrual() {
+            fund_default_accounts();
+            let alice = default_accounts::<ink_env::DefaultEnvironment>().alice;
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(alice);
 
-            let to_be_claimed = staking_contract_instance.rewards_for_user(alice);
-            assert_eq!(0, to_be_claimed);
+            // 1_000_000 at 10% a year earns 0.019 a block at the nominal 6 second block time
+            let mut staking_contract_instance = Staking::new(1000);
+            assert_eq!(
+                staking_contract_instance.set_accrual_basis(AccrualBasis::Timestamp),
+                Ok(())
+            );
+            let _ = ink_env::pay_with_call!(staking_contract_instance.fund_rewards(), 100);
+            let _ = ink_env::pay_with_call!(staking_contract_instance.stake(), 1_000_000);
+            assert_eq!(
+                staking_contract_instance.blocks_until_next_reward_unit(alice),
+                Some(53)
+            );
+            assert_eq!(staking_contract_instance.pool_runway_blocks(), Some(5256));
 
-            let alice_balance = get_account_balance::<ink_env::DefaultEnvironment>(alice).unwrap();
-            assert_eq!(1000025, alice_balance);
+            // the off-chain blocks only take 6 milliseconds, a thousandth of the reward
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            assert_eq!(
+                staking_contract_instance.blocks_until_next_reward_unit(alice),
+                Some(52_560)
+            );
+            assert_eq!(
+                staking_contract_instance.pool_runway_blocks(),
+                Some(5_256_000)
+            );
         }
     }
 }